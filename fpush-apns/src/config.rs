@@ -15,6 +15,9 @@ pub struct AppleApnsConfig {
     pool_idle_timeout: u64,
     #[serde(default = "AppleApnsConfig::default_request_timeout")]
     request_timeout: u64,
+    /// set the app badge to the `message-count` of the push summary
+    #[serde(default)]
+    badge_from_message_count: bool,
 }
 
 impl AppleApnsConfig {
@@ -49,6 +52,10 @@ impl AppleApnsConfig {
         self.request_timeout
     }
 
+    pub fn badge_from_message_count(&self) -> bool {
+        self.badge_from_message_count
+    }
+
     pub fn default_pool_timeout() -> u64 {
         600
     }
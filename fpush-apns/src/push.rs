@@ -5,7 +5,7 @@ use a2::{
     request::payload::PayloadLike, Client, ClientConfig, DefaultNotificationBuilder,
     NotificationBuilder, NotificationOptions, Priority, PushType,
 };
-use fpush_traits::push::{PushError, PushResult, PushTrait};
+use fpush_traits::push::{PushError, PushResult, PushSummary, PushTrait};
 
 use async_trait::async_trait;
 use log::{debug, error};
@@ -16,15 +16,15 @@ pub struct FpushApns {
     apns: a2::client::Client,
     topic: String,
     additional_data: Option<HashMap<String, Value>>,
+    badge_from_message_count: bool,
 }
 
 impl FpushApns {
     fn open_cert(filename: &str) -> PushResult<std::fs::File> {
-        if let Ok(file) = std::fs::File::open(filename) {
-            Ok(file)
-        } else {
-            Err(PushError::CertLoading)
-        }
+        std::fs::File::open(filename).map_err(|e| PushError::CertLoading {
+            path: filename.to_string(),
+            reason: e.to_string(),
+        })
     }
 
     pub fn init(apns_config: &AppleApnsConfig) -> PushResult<Self> {
@@ -40,17 +40,14 @@ impl FpushApns {
                     apns: apns_conn,
                     topic: apns_config.topic().to_string(),
                     additional_data: apns_config.additional_data().clone(),
+                    badge_from_message_count: apns_config.badge_from_message_count(),
                 };
                 Ok(wrapped_conn)
             }
-            Err(a2::error::Error::ReadError(e)) => {
-                error!("Could not read apns: {}", e);
-                Err(PushError::PushEndpointPersistent)
-            }
-            Err(e) => {
-                error!("Problem initializing apple config: {}", e);
-                Err(PushError::PushEndpointTmp)
-            }
+            Err(e) => Err(PushError::CertLoading {
+                path: apns_config.cert_file_path().to_string(),
+                reason: e.to_string(),
+            }),
         }
     }
 }
@@ -58,12 +55,18 @@ impl FpushApns {
 #[async_trait]
 impl PushTrait for FpushApns {
     #[inline(always)]
-    async fn send(&self, token: String) -> PushResult<()> {
-        let notification_builder = DefaultNotificationBuilder::new()
+    async fn send(&self, token: String, summary: &PushSummary) -> PushResult<()> {
+        let mut notification_builder = DefaultNotificationBuilder::new()
             .set_title("New Message")
             .set_body("New Message?")
             .set_mutable_content()
             .set_sound("default");
+        if let Some(message_count) = summary
+            .message_count
+            .filter(|_| self.badge_from_message_count)
+        {
+            notification_builder = notification_builder.set_badge(message_count);
+        }
         let mut payload = notification_builder.build(
             &token,
             NotificationOptions {
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, path::Path};
 
-use fpush_traits::push::{PushError, PushResult, PushTrait};
+use fpush_traits::push::{PushError, PushResult, PushSummary, PushTrait};
 
 use async_trait::async_trait;
 use google_fcm1::{
@@ -20,19 +20,28 @@ pub struct FpushFcm {
 }
 
 impl FpushFcm {
-    async fn load_oauth2_app_secret(fcm_config: &GoogleFcmConfig) -> yup_oauth2::ServiceAccountKey {
-        match yup_oauth2::read_service_account_key(Path::new(fcm_config.fcm_secret_path())).await {
-            Ok(s) => s,
-            Err(e) => panic!(
-                "Could not read fcm config file at {} reason: {}",
-                fcm_config.fcm_secret_path(),
-                e
-            ),
-        }
+    async fn load_oauth2_app_secret(
+        fcm_config: &GoogleFcmConfig,
+    ) -> PushResult<yup_oauth2::ServiceAccountKey> {
+        yup_oauth2::read_service_account_key(Path::new(fcm_config.fcm_secret_path()))
+            .await
+            .map_err(|e| PushError::CertLoading {
+                path: fcm_config.fcm_secret_path().to_string(),
+                reason: e.to_string(),
+            })
     }
 
     pub async fn init(fcm_config: &GoogleFcmConfig) -> PushResult<Self> {
-        let fcm_secret = Self::load_oauth2_app_secret(fcm_config).await;
+        let fcm_secret = Self::load_oauth2_app_secret(fcm_config).await?;
+        let project_id = match &fcm_secret.project_id {
+            Some(project_id) => project_id.clone(),
+            None => {
+                return Err(PushError::CertLoading {
+                    path: fcm_config.fcm_secret_path().to_string(),
+                    reason: "service account key has no project_id".to_string(),
+                })
+            }
+        };
 
         // create login auth object
         let auth = match yup_oauth2::ServiceAccountAuthenticator::builder(fcm_secret.clone())
@@ -41,8 +50,10 @@ impl FpushFcm {
         {
             Ok(auth) => auth,
             Err(e) => {
-                error!("Could not load fcm DeviceFlowAuthenticator: {}", e);
-                return Err(PushError::CertLoading);
+                return Err(PushError::CertLoading {
+                    path: fcm_config.fcm_secret_path().to_string(),
+                    reason: format!("could not build authenticator: {}", e),
+                })
             }
         };
 
@@ -59,7 +70,7 @@ impl FpushFcm {
         let fcm_conn = FirebaseCloudMessaging::new(hyper_client, auth);
         Ok(Self {
             fcm_conn,
-            fcm_parent: format!("projects/{}", fcm_secret.project_id.unwrap()),
+            fcm_parent: format!("projects/{}", project_id),
         })
     }
 }
@@ -104,9 +115,9 @@ enum FcmErrorCode {
 #[async_trait]
 impl PushTrait for FpushFcm {
     #[inline(always)]
-    async fn send(&self, token: String) -> PushResult<()> {
+    async fn send(&self, token: String, summary: &PushSummary) -> PushResult<()> {
         let req = SendMessageRequest {
-            message: Some(create_push_message(token)),
+            message: Some(create_push_message(token, summary)),
             validate_only: None,
         };
 
@@ -151,9 +162,20 @@ impl PushTrait for FpushFcm {
 }
 
 #[inline(always)]
-fn create_push_message(token: String) -> Message {
+fn create_push_message(token: String, summary: &PushSummary) -> Message {
+    // sender and body are not forwarded to google, only counters
+    let mut data = HashMap::new();
+    if let Some(message_count) = summary.message_count {
+        data.insert("messageCount".to_string(), message_count.to_string());
+    }
+    if let Some(pending_subscription_count) = summary.pending_subscription_count {
+        data.insert(
+            "pendingSubscriptionCount".to_string(),
+            pending_subscription_count.to_string(),
+        );
+    }
     Message {
-        data: Some(HashMap::new()),
+        data: Some(data),
         token: Some(token),
         ..Default::default()
     }
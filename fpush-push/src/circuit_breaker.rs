@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::fpush_config::CircuitBreakerSettings;
+use fpush_traits::push::{PushError, PushResult};
+
+use log::{info, warn};
+
+enum BreakerState {
+    Closed {
+        consecutive_failures: u32,
+        /// outcomes of the latest pushes, true for failures
+        window: VecDeque<bool>,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        in_flight: u32,
+        successes: u32,
+    },
+}
+
+impl BreakerState {
+    fn closed(window_size: usize) -> Self {
+        BreakerState::Closed {
+            consecutive_failures: 0,
+            window: VecDeque::with_capacity(window_size),
+        }
+    }
+}
+
+/// Stops calling a push backend during an outage.
+/// Opens after too many backend failures, fails fast until the cooldown elapsed and
+/// closes again once the probe requests of the half-open state succeeded.
+pub(crate) struct CircuitBreaker {
+    identifier: String,
+    enabled: bool,
+    consecutive_failures: u32,
+    failure_rate: f64,
+    window_size: usize,
+    cooldown: Duration,
+    probes: u32,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(identifier: &str, settings: &CircuitBreakerSettings) -> Self {
+        let window_size = (*settings.window_size()).max(1) as usize;
+        Self {
+            identifier: identifier.to_string(),
+            enabled: *settings.enabled(),
+            consecutive_failures: (*settings.consecutive_failures()).max(1),
+            failure_rate: settings.failure_rate().clamp(0.0, 1.0),
+            window_size,
+            cooldown: *settings.cooldown(),
+            probes: (*settings.probes()).max(1),
+            state: Mutex::new(BreakerState::closed(window_size)),
+        }
+    }
+
+    /// Check if a push may be sent to the backend.
+    /// In the half-open state only the configured number of probes is let through,
+    /// the returned permit keeps the probe slot until its result is recorded or it is dropped
+    pub(crate) fn try_acquire(&self) -> Option<BreakerPermit<'_>> {
+        if !self.enabled {
+            return Some(BreakerPermit::new(self, false));
+        }
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return Some(BreakerPermit::new(self, false)),
+        };
+        match &mut *state {
+            BreakerState::Closed { .. } => Some(BreakerPermit::new(self, false)),
+            BreakerState::Open { until } => {
+                if Instant::now() < *until {
+                    return None;
+                }
+                info!(
+                    "{}: Circuit breaker half-open, sending probe requests",
+                    self.identifier
+                );
+                *state = BreakerState::HalfOpen {
+                    in_flight: 1,
+                    successes: 0,
+                };
+                Some(BreakerPermit::new(self, true))
+            }
+            BreakerState::HalfOpen { in_flight, .. } => {
+                if *in_flight < self.probes {
+                    *in_flight += 1;
+                    Some(BreakerPermit::new(self, true))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Free the probe slot of a request that ended without reaching the backend
+    fn release_probe(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if let BreakerState::HalfOpen { in_flight, .. } = &mut *state {
+                *in_flight = in_flight.saturating_sub(1);
+            }
+        }
+    }
+
+    /// True if pushes are sent without restrictions
+    pub(crate) fn is_closed(&self) -> bool {
+        !self.enabled
+            || self
+                .state
+                .lock()
+                .is_ok_and(|state| matches!(*state, BreakerState::Closed { .. }))
+    }
+
+    /// Track the outcome of a push request
+    fn record(&self, result: &PushResult<()>) {
+        if !self.enabled {
+            return;
+        }
+        let failed = matches!(
+            result,
+            Err(PushError::PushEndpointTmp) | Err(PushError::PushEndpointPersistent)
+        );
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        match &mut *state {
+            BreakerState::Closed {
+                consecutive_failures,
+                window,
+            } => {
+                if window.len() == self.window_size {
+                    window.pop_front();
+                }
+                window.push_back(failed);
+                if !failed {
+                    *consecutive_failures = 0;
+                    return;
+                }
+                *consecutive_failures += 1;
+                let failures = window.iter().filter(|failed| **failed).count();
+                let rate = failures as f64 / window.len() as f64;
+                if *consecutive_failures >= self.consecutive_failures {
+                    warn!(
+                        "{}: Circuit breaker opened after {} consecutive backend failures",
+                        self.identifier, consecutive_failures
+                    );
+                } else if window.len() == self.window_size && rate >= self.failure_rate {
+                    warn!(
+                        "{}: Circuit breaker opened after {} of the last {} pushes failed",
+                        self.identifier,
+                        failures,
+                        window.len()
+                    );
+                } else {
+                    return;
+                }
+                *state = BreakerState::Open {
+                    until: Instant::now() + self.cooldown,
+                };
+            }
+            // late results of pushes started before the circuit opened
+            BreakerState::Open { .. } => {}
+            BreakerState::HalfOpen {
+                in_flight,
+                successes,
+            } => {
+                if failed {
+                    warn!(
+                        "{}: Circuit breaker reopened after a failed probe request",
+                        self.identifier
+                    );
+                    *state = BreakerState::Open {
+                        until: Instant::now() + self.cooldown,
+                    };
+                    return;
+                }
+                *in_flight = in_flight.saturating_sub(1);
+                *successes += 1;
+                if *successes >= self.probes {
+                    info!(
+                        "{}: Circuit breaker closed after successful probe requests",
+                        self.identifier
+                    );
+                    *state = BreakerState::closed(self.window_size);
+                }
+            }
+        }
+    }
+}
+
+/// Permission to send a push, returned by [`CircuitBreaker::try_acquire`].
+/// Dropping a probe permit without recording a result frees its half-open slot again,
+/// e.g. if the request was ratelimited afterwards or its future was cancelled.
+pub(crate) struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+}
+
+impl<'a> BreakerPermit<'a> {
+    fn new(breaker: &'a CircuitBreaker, probe: bool) -> Self {
+        Self { breaker, probe }
+    }
+
+    /// Track the final outcome of a push request
+    pub(crate) fn record(mut self, result: &PushResult<()>) {
+        self.breaker.record(result);
+        self.probe = false;
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.release_probe();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(settings: &str) -> CircuitBreaker {
+        let settings: CircuitBreakerSettings = serde_json::from_str(settings).unwrap();
+        CircuitBreaker::new("test", &settings)
+    }
+
+    #[test]
+    fn disabled_never_opens() {
+        let breaker = breaker(r#"{"enabled": false}"#);
+        for _ in 0..100 {
+            breaker.record(&Err(PushError::PushEndpointTmp));
+        }
+        assert!(breaker.try_acquire().is_some());
+        assert!(breaker.is_closed());
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = breaker(
+            r#"{"enabled": true, "consecutiveFailures": 3, "windowSize": 100, "cooldown": "1h"}"#,
+        );
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        breaker.record(&Err(PushError::PushEndpointPersistent));
+        // errors caused by the token do not count as backend failures
+        breaker.record(&Err(PushError::TokenBlocked));
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        assert!(breaker.try_acquire().is_some());
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        assert!(!breaker.is_closed());
+        assert!(breaker.try_acquire().is_none());
+    }
+
+    #[test]
+    fn opens_on_failure_rate() {
+        let breaker = breaker(
+            r#"{"enabled": true, "consecutiveFailures": 100, "failureRate": 0.5, "windowSize": 4, "cooldown": "1h"}"#,
+        );
+        for result in [Ok(()), Err(PushError::PushEndpointTmp), Ok(())] {
+            breaker.record(&result);
+        }
+        assert!(breaker.is_closed());
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        assert!(breaker.try_acquire().is_none());
+    }
+
+    #[test]
+    fn half_open_probes() {
+        let breaker = breaker(
+            r#"{"enabled": true, "consecutiveFailures": 1, "cooldown": "0s", "probes": 2}"#,
+        );
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        assert!(!breaker.is_closed());
+
+        // a failed probe opens the circuit again
+        breaker
+            .try_acquire()
+            .unwrap()
+            .record(&Err(PushError::PushEndpointTmp));
+        assert!(!breaker.is_closed());
+
+        let first = breaker.try_acquire().unwrap();
+        let second = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_none());
+        first.record(&Ok(()));
+        assert!(!breaker.is_closed());
+        second.record(&Ok(()));
+        assert!(breaker.is_closed());
+    }
+
+    #[test]
+    fn dropped_probe_frees_slot() {
+        let breaker = breaker(
+            r#"{"enabled": true, "consecutiveFailures": 1, "cooldown": "0s", "probes": 1}"#,
+        );
+        breaker.record(&Err(PushError::PushEndpointTmp));
+        let probe = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_none());
+        drop(probe);
+        breaker.try_acquire().unwrap().record(&Ok(()));
+        assert!(breaker.is_closed());
+    }
+}
@@ -5,12 +5,97 @@ pub type PushRequestResult<T> = std::result::Result<T, PushRequestError>;
 #[derive(Debug, From, Display)]
 pub enum PushRequestError {
     TokenRatelimited,
+    DomainRatelimited,
     TokenBlocked,
+    TokenUnregistered,
     Internal,
     UnknownPushModule,
+    ModuleUnavailable,
+    NotAuthorized,
+    CircuitOpen,
+}
+
+impl PushRequestError {
+    /// Short machine readable name of the outcome
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PushRequestError::TokenRatelimited => "token-ratelimited",
+            PushRequestError::DomainRatelimited => "domain-ratelimited",
+            PushRequestError::TokenBlocked => "token-blocked",
+            PushRequestError::TokenUnregistered => "token-unregistered",
+            PushRequestError::Internal => "push-backend-error",
+            PushRequestError::UnknownPushModule => "unknown-push-module",
+            PushRequestError::ModuleUnavailable => "push-module-unavailable",
+            PushRequestError::NotAuthorized => "not-authorized",
+            PushRequestError::CircuitOpen => "circuit-open",
+        }
+    }
+
+    /// Human readable description used as error text of replies
+    pub fn description(&self) -> &'static str {
+        match self {
+            PushRequestError::TokenRatelimited => "Too many push requests for this token",
+            PushRequestError::DomainRatelimited => "Too many push requests from your domain",
+            PushRequestError::TokenBlocked => "The push token is blocked",
+            PushRequestError::TokenUnregistered => "The push token was unregistered",
+            PushRequestError::Internal => "The push backend failed to deliver the push",
+            PushRequestError::UnknownPushModule => "Unknown push module",
+            PushRequestError::ModuleUnavailable => "The push module failed to load",
+            PushRequestError::NotAuthorized => "Missing or wrong push secret",
+            PushRequestError::CircuitOpen => "The push backend is currently unavailable",
+        }
+    }
 }
 
 #[derive(Debug, From, Display)]
 pub(crate) enum Error {
     PushErrors(fpush_traits::push::PushError),
 }
+
+/// Reason a configured push module could not be loaded
+#[derive(Debug, Display)]
+pub enum ModuleLoadError {
+    #[display("could not initialize push backend: {_0}")]
+    Init(fpush_traits::push::PushError),
+    #[display("identifier {_0} is already used by push module {_1}")]
+    DuplicateIdentifier(String, String),
+}
+
+/// All push modules that could not be loaded
+#[derive(Debug)]
+pub struct LoadError {
+    failed_modules: Vec<(String, ModuleLoadError)>,
+}
+
+impl LoadError {
+    pub(crate) fn new(failed_modules: Vec<(String, ModuleLoadError)>) -> Self {
+        Self { failed_modules }
+    }
+
+    /// Identifier and failure reason of each push module that could not be loaded
+    pub fn failed_modules(&self) -> &[(String, ModuleLoadError)] {
+        &self.failed_modules
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} push module(s) failed to load",
+            self.failed_modules.len()
+        )?;
+        for (module_id, e) in &self.failed_modules {
+            write!(f, "\n  {}: {}", module_id, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Error> for ModuleLoadError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::PushErrors(e) => ModuleLoadError::Init(e),
+        }
+    }
+}
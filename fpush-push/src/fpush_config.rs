@@ -1,8 +1,11 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use fpush_ratelimit::RatelimitSettings;
+use crate::reply_policy::ReplyPolicy;
+use fpush_ratelimit::{DomainLimitSettings, RatelimitSettings};
 use fpush_tokenblocker::BlacklistSettings;
 
+use derive_getters::Getters;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -35,30 +38,155 @@ pub enum PushConfig {
     #[cfg(feature = "enable_apns_support")]
     Apple {
         apns: fpush_apns::AppleApnsConfig,
-        #[serde(default)]
-        blacklist: BlacklistSettings,
-        #[serde(default)]
-        ratelimit: RatelimitSettings,
-        #[serde(default)]
-        is_default_module: bool,
+        #[serde(flatten)]
+        settings: PushModuleSettings,
     },
     #[cfg(feature = "enable_fcm_support")]
     Google {
         fcm: fpush_fcm::GoogleFcmConfig,
-        #[serde(default)]
-        blacklist: BlacklistSettings,
-        #[serde(default)]
-        ratelimit: RatelimitSettings,
-        #[serde(default)]
-        is_default_module: bool,
+        #[serde(flatten)]
+        settings: PushModuleSettings,
     },
     #[cfg(feature = "enable_demo_support")]
     Demo {
-        #[serde(default)]
-        blacklist: BlacklistSettings,
-        #[serde(default)]
-        ratelimit: RatelimitSettings,
-        #[serde(default)]
-        is_default_module: bool,
+        #[serde(flatten)]
+        settings: PushModuleSettings,
     },
 }
+
+impl PushConfig {
+    /// Settings shared by all push module types
+    pub fn settings(&self) -> &PushModuleSettings {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushConfig::Apple { settings, .. } => settings,
+            #[cfg(feature = "enable_fcm_support")]
+            PushConfig::Google { settings, .. } => settings,
+            #[cfg(feature = "enable_demo_support")]
+            PushConfig::Demo { settings } => settings,
+        }
+    }
+}
+
+/// Settings shared by all push module types
+#[derive(Debug, Deserialize, Getters)]
+pub struct PushModuleSettings {
+    #[serde(default)]
+    blacklist: BlacklistSettings,
+    #[serde(default)]
+    ratelimit: RatelimitSettings,
+    #[serde(default)]
+    domain_limit: DomainLimitSettings,
+    #[serde(default)]
+    is_default_module: bool,
+    /// further identifiers the push module is reachable with
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    secret: Option<PushSecretSettings>,
+    #[serde(default)]
+    reply_policy: ReplyPolicy,
+    #[serde(default)]
+    random_delay: RandomDelaySettings,
+    #[serde(default)]
+    retry: RetrySettings,
+    #[serde(default)]
+    circuit_breaker: CircuitBreakerSettings,
+}
+
+/// Server key used to verify the XEP-0357 `secret` publish-option
+#[derive(Debug, Deserialize, Getters)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSecretSettings {
+    key: String,
+    /// include the domain of the requesting XMPP server into the HMAC
+    #[serde(default)]
+    include_origin_domain: bool,
+}
+
+/// Range of the random delay before the ratelimit check,
+/// only used if built with the `random_delay_before_push` feature
+#[derive(Debug, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RandomDelaySettings {
+    #[serde(deserialize_with = "serde_humantime")]
+    min: Duration,
+    #[serde(deserialize_with = "serde_humantime")]
+    max: Duration,
+}
+
+impl Default for RandomDelaySettings {
+    fn default() -> Self {
+        Self {
+            min: Duration::ZERO,
+            max: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Retries of pushes that failed with a temporary error of the push backend
+#[derive(Debug, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetrySettings {
+    /// attempts including the first one, 1 disables retries
+    max_attempts: u32,
+    #[serde(deserialize_with = "serde_humantime")]
+    initial_delay: Duration,
+    multiplier: f64,
+    #[serde(deserialize_with = "serde_humantime")]
+    max_delay: Duration,
+    /// no retry is started later than this after the first attempt
+    #[serde(deserialize_with = "serde_humantime")]
+    deadline: Duration,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_delay: Duration::from_millis(200),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(2),
+            deadline: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Fail fast while the push backend fails, disabled by default
+#[derive(Debug, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CircuitBreakerSettings {
+    enabled: bool,
+    /// consecutive backend failures opening the circuit
+    consecutive_failures: u32,
+    /// share of failed pushes within the window opening the circuit
+    failure_rate: f64,
+    /// number of latest pushes the failure rate is calculated over
+    window_size: u32,
+    /// time the circuit stays open before probe requests are sent
+    #[serde(deserialize_with = "serde_humantime")]
+    cooldown: Duration,
+    /// successful probe requests needed to close the circuit again
+    probes: u32,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            consecutive_failures: 10,
+            failure_rate: 0.5,
+            window_size: 50,
+            cooldown: Duration::from_secs(30),
+            probes: 1,
+        }
+    }
+}
+
+pub fn serde_humantime<'de, D>(deserializer: D) -> std::result::Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    serde_humantime::De::<Duration>::deserialize(deserializer)
+        .map(|wrapped_de: serde_humantime::De<Duration>| wrapped_de.into_inner())
+}
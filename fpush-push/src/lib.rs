@@ -1,106 +1,455 @@
+mod circuit_breaker;
 mod error;
-pub use error::{PushRequestError, PushRequestResult};
+pub use error::{LoadError, ModuleLoadError, PushRequestError, PushRequestResult};
 mod fpush_config;
+pub use fpush_config::CircuitBreakerSettings;
 pub use fpush_config::FpushPushConfig;
 pub use fpush_config::PushConfig;
+pub use fpush_config::PushModuleSettings;
+pub use fpush_config::PushSecretSettings;
+pub use fpush_config::RandomDelaySettings;
+pub use fpush_config::RetrySettings;
+pub use fpush_traits::push::PushSummary;
 
 mod push_handler;
 pub use push_handler::handle_push_request;
+use push_handler::handle_unregister_request;
 mod push_module;
+mod push_request;
+#[cfg(feature = "random_delay_before_push")]
+mod random_delay;
+mod reply_policy;
+mod retry;
+pub use reply_policy::{ReplyAction, ReplyErrorCondition, ReplyErrorType, ReplyPolicy};
+mod secret;
+pub use push_request::PushRequest;
+mod stats;
+pub use stats::PushModuleStatsSnapshot;
 
-use dashmap::DashMap;
-use push_module::{PushModule, PushModuleEnum, PushModuleMapArc};
-use std::sync::Arc;
+use push_module::{PushModule, PushModuleEnum, PushModuleMapArc, PushModuleState};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 
-use log::{debug, info};
+use log::{debug, error, info};
 
 pub type FpushPushArc = Arc<FpushPush>;
 
 pub struct FpushPush {
-    push_modules: PushModuleMapArc,
+    /// replaced as a whole on reload, requests keep the set they started with
+    push_modules: RwLock<PushModuleMapArc>,
+    /// identifiers of push modules that failed to load during a degraded start or reload
+    unavailable_modules: RwLock<HashSet<String>>,
+    /// push modules that failed to load and the reason
+    failed_modules: RwLock<Vec<(String, String)>>,
+}
+
+/// Result of loading all configured push modules
+#[derive(Default)]
+struct LoadedModules {
+    push_modules: HashMap<String, Arc<PushModuleEnum>>,
+    unavailable_modules: HashSet<String>,
+    failed_modules: Vec<(String, ModuleLoadError)>,
+}
+
+impl LoadedModules {
+    fn failure_reasons(&self) -> Vec<(String, String)> {
+        self.failed_modules
+            .iter()
+            .map(|(module_id, e)| (module_id.clone(), e.to_string()))
+            .collect()
+    }
+
+    /// True if all push modules were loaded, or a degraded start is allowed
+    /// and at least one push module is left
+    fn is_usable(&self, allow_degraded_start: bool) -> bool {
+        self.failed_modules.is_empty() || (allow_degraded_start && !self.push_modules.is_empty())
+    }
 }
 
 impl FpushPush {
-    pub async fn new(module_config: &FpushPushConfig) -> Self {
-        let mut a = Self {
-            push_modules: Arc::new(DashMap::default()),
-        };
-        a.load_push_modules(module_config).await;
-        a
-    }
-
-    async fn load_push_modules(&mut self, module_config: &FpushPushConfig) {
-        let mut default_counter = 0;
-        for (push_module_id, module_config) in module_config.config() {
-            let (is_default_module, push_module) =
-                Self::init_push_module(push_module_id.clone(), module_config).await;
-            self.push_modules
-                .insert(push_module_id.to_string(), push_module);
-            if is_default_module {
-                default_counter += 1;
-                info!("Loading {} as default push module", push_module_id);
-                let (_, push_module) =
-                    Self::init_push_module(push_module_id.clone(), module_config).await;
-                self.push_modules.insert("default".to_string(), push_module);
+    /// Load all configured push modules.
+    /// If `allow_degraded_start` is set, push modules failing to load are skipped
+    /// as long as at least one push module could be loaded.
+    pub async fn new(
+        module_config: &FpushPushConfig,
+        allow_degraded_start: bool,
+    ) -> std::result::Result<Self, LoadError> {
+        let loaded = Self::load_push_modules(module_config, &PushModuleMapArc::default()).await;
+        if !loaded.is_usable(allow_degraded_start) {
+            return Err(LoadError::new(loaded.failed_modules));
+        }
+        for (module_id, e) in &loaded.failed_modules {
+            error!("Starting without push module {}: {}", module_id, e);
+        }
+        Ok(Self {
+            failed_modules: RwLock::new(loaded.failure_reasons()),
+            push_modules: RwLock::new(Arc::new(loaded.push_modules.into_iter().collect())),
+            unavailable_modules: RwLock::new(loaded.unavailable_modules),
+        })
+    }
+
+    /// Replace all push modules by the ones of `module_config`.
+    /// Push modules keep their ratelimit and blocklist state if the respective settings did
+    /// not change. The new push modules replace the current ones in a single step, removed
+    /// push modules are dropped once their last request finished.
+    /// If loading fails, the current push modules are kept.
+    pub async fn reload(
+        &self,
+        module_config: &FpushPushConfig,
+        allow_degraded_start: bool,
+    ) -> std::result::Result<(), LoadError> {
+        let loaded = Self::load_push_modules(module_config, &self.push_modules()).await;
+        if !loaded.is_usable(allow_degraded_start) {
+            return Err(LoadError::new(loaded.failed_modules));
+        }
+        for (module_id, e) in &loaded.failed_modules {
+            error!("Reloading without push module {}: {}", module_id, e);
+        }
+        let failure_reasons = loaded.failure_reasons();
+        let identifier_count = loaded.push_modules.len();
+        let push_modules = Arc::new(loaded.push_modules.into_iter().collect());
+        match self.push_modules.write() {
+            Ok(mut current) => *current = push_modules,
+            Err(poisoned) => *poisoned.into_inner() = push_modules,
+        }
+        if let Ok(mut failed_modules) = self.failed_modules.write() {
+            *failed_modules = failure_reasons;
+        }
+        if let Ok(mut unavailable_modules) = self.unavailable_modules.write() {
+            *unavailable_modules = loaded.unavailable_modules;
+        }
+        info!("Reloaded {} push module identifiers", identifier_count);
+        Ok(())
+    }
+
+    /// Load all push modules, taking over the state of the matching `previous` push modules
+    async fn load_push_modules(
+        module_config: &FpushPushConfig,
+        previous: &PushModuleMapArc,
+    ) -> LoadedModules {
+        let mut loaded = LoadedModules::default();
+        // sorted to decide deterministically which push module keeps a duplicate identifier
+        let mut module_configs: Vec<_> = module_config.config().iter().collect();
+        module_configs.sort_by_key(|(push_module_id, _)| *push_module_id);
+        let mut claimed_identifiers: HashMap<String, String> = HashMap::new();
+        for (push_module_id, module_config) in module_configs {
+            let identifiers = Self::module_identifiers(push_module_id, module_config.settings());
+            let duplicate = identifiers.iter().find_map(|identifier| {
+                claimed_identifiers
+                    .get(identifier)
+                    .map(|owner| (identifier.clone(), owner.clone()))
+            });
+            if let Some((identifier, owner)) = duplicate {
+                loaded.unavailable_modules.extend(
+                    identifiers
+                        .into_iter()
+                        .filter(|identifier| !claimed_identifiers.contains_key(identifier)),
+                );
+                loaded.failed_modules.push((
+                    push_module_id.clone(),
+                    ModuleLoadError::DuplicateIdentifier(identifier, owner),
+                ));
+                continue;
+            }
+            for identifier in &identifiers {
+                claimed_identifiers.insert(identifier.clone(), push_module_id.clone());
+            }
+
+            let state = match previous.get(push_module_id) {
+                Some(previous) if previous.identifier() == push_module_id => {
+                    previous.reusable_state(module_config.settings())
+                }
+                _ => PushModuleState::default(),
+            };
+            if !state.is_empty() {
+                info!("Keeping token state of push module {}", push_module_id);
+            }
+            match Self::init_push_module(push_module_id.clone(), module_config, state).await {
+                Ok(push_module) => {
+                    let push_module = Arc::new(push_module);
+                    for identifier in identifiers {
+                        if identifier == "default" {
+                            info!("Loading {} as default push module", push_module_id);
+                        } else if identifier != *push_module_id {
+                            info!(
+                                "Loading {} as alias of push module {}",
+                                identifier, push_module_id
+                            );
+                        }
+                        loaded.push_modules.insert(identifier, push_module.clone());
+                    }
+                }
+                Err(e) => {
+                    loaded.unavailable_modules.extend(identifiers);
+                    loaded.failed_modules.push((push_module_id.clone(), e));
+                }
             }
         }
-        if default_counter > 1 {
-            panic!("At most one push module can be defined as the default module");
+        loaded
+    }
+
+    /// Identifiers a push module is requested with: its own, "default" and its aliases
+    fn module_identifiers(push_module_id: &str, settings: &PushModuleSettings) -> Vec<String> {
+        let mut identifiers = vec![push_module_id.to_string()];
+        if *settings.is_default_module() {
+            identifiers.push("default".to_string());
         }
+        identifiers.extend(settings.aliases().iter().cloned());
+        identifiers
     }
 
     /// Load and init push module using the provided configuration
-    /// Return true if the push module is the default push module
-    async fn init_push_module(key: String, module_config: &PushConfig) -> (bool, PushModuleEnum) {
-        match module_config {
+    async fn init_push_module(
+        key: String,
+        module_config: &PushConfig,
+        state: PushModuleState,
+    ) -> std::result::Result<PushModuleEnum, ModuleLoadError> {
+        let push_module = match module_config {
             #[cfg(feature = "enable_apns_support")]
-            PushConfig::Apple {
-                apns,
-                blacklist,
-                ratelimit,
-                is_default_module,
-            } => {
-                let apple_push_module =
-                    PushModule::new_apple_module(key.clone(), apns, blacklist, ratelimit).unwrap();
-                (*is_default_module, PushModuleEnum::Apple(apple_push_module))
+            PushConfig::Apple { apns, settings } => {
+                PushModuleEnum::Apple(PushModule::new_apple_module(key, apns, settings, state)?)
             }
             #[cfg(feature = "enable_fcm_support")]
-            PushConfig::Google {
-                fcm,
-                blacklist,
-                ratelimit,
-                is_default_module,
-            } => {
-                let google_fcm_push_module =
-                    PushModule::new_fcm_module(key.clone(), fcm, blacklist, ratelimit)
-                        .await
-                        .unwrap();
-                (
-                    *is_default_module,
-                    PushModuleEnum::Google(google_fcm_push_module),
-                )
+            PushConfig::Google { fcm, settings } => {
+                PushModuleEnum::Google(PushModule::new_fcm_module(key, fcm, settings, state).await?)
             }
             #[cfg(feature = "enable_demo_support")]
-            PushConfig::Demo {
-                blacklist,
-                ratelimit,
-                is_default_module,
-            } => {
-                let demo = PushModule::new_demo_module(key.clone(), blacklist, ratelimit)
-                    .await
-                    .unwrap();
-                (*is_default_module, PushModuleEnum::Demo(demo))
+            PushConfig::Demo { settings } => {
+                PushModuleEnum::Demo(PushModule::new_demo_module(key, settings, state).await?)
             }
+        };
+        Ok(push_module)
+    }
+
+    /// Current set of push modules
+    #[inline(always)]
+    fn push_modules(&self) -> PushModuleMapArc {
+        match self.push_modules.read() {
+            Ok(push_modules) => push_modules.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Push module registered with the given identifier or alias
+    #[inline(always)]
+    fn push_module(&self, module_id: &str) -> Option<Arc<PushModuleEnum>> {
+        self.push_modules()
+            .get(module_id)
+            .map(|push_module| push_module.value().clone())
+    }
+
+    /// True if the push module failed to load
+    fn is_unavailable(&self, module_id: &str) -> bool {
+        self.unavailable_modules
+            .read()
+            .is_ok_and(|unavailable_modules| unavailable_modules.contains(module_id))
+    }
+
+    /// Identifiers of all configured push modules, without the "default" module and aliases
+    pub fn module_ids(&self) -> Vec<String> {
+        let mut module_ids: Vec<String> = self
+            .push_modules()
+            .iter()
+            .filter(|push_module| push_module.key() == push_module.identifier())
+            .map(|push_module| push_module.key().to_string())
+            .collect();
+        module_ids.sort();
+        module_ids
+    }
+
+    /// Identifier and failure reason of the push modules that could not be loaded
+    pub fn failed_modules(&self) -> Vec<(String, String)> {
+        self.failed_modules
+            .read()
+            .map(|failed_modules| failed_modules.clone())
+            .unwrap_or_default()
+    }
+
+    /// Type (apple, google, demo) of the push module with the given identifier
+    pub fn module_type(&self, module_id: &str) -> Option<&'static str> {
+        self.push_modules()
+            .get(module_id)
+            .map(|push_module| push_module.value().module_type())
+    }
+
+    /// Counters of the push module with the given identifier
+    pub fn module_stats(&self, module_id: &str) -> Option<PushModuleStatsSnapshot> {
+        self.push_modules()
+            .get(module_id)
+            .map(|push_module| push_module.value().stats().snapshot())
+    }
+
+    /// Look up the ratelimit and blocklist state of a token without modifying it
+    pub fn token_status(&self, module_id: &str, token: &str) -> PushRequestResult<TokenStatus> {
+        match self.push_modules().get(module_id) {
+            Some(push_module) => Ok(TokenStatus {
+                ratelimited: push_module.ratelimit().is_ratelimited(token),
+                blocked_until: push_module.blocklist().blocked_until(token),
+            }),
+            None => Err(PushRequestError::UnknownPushModule),
+        }
+    }
+
+    /// Remove a token from the blocklist of a push module. Returns true if the token was blocked
+    pub fn unblock_token(&self, module_id: &str, token: &str) -> PushRequestResult<bool> {
+        match self.push_modules().get(module_id) {
+            Some(push_module) => Ok(push_module.blocklist().unblock(token)),
+            None => Err(PushRequestError::UnknownPushModule),
+        }
+    }
+
+    /// Forget a token of the push module after its pubsub node was deleted, purged or retracted.
+    /// The request has to carry a valid `secret` if the push module requires one
+    pub fn unregister_token(
+        &self,
+        module_id: &str,
+        request: &PushRequest,
+    ) -> PushRequestResult<()> {
+        match self.push_module(module_id) {
+            Some(push_module) => handle_unregister_request(&push_module, request),
+            None if self.is_unavailable(module_id) => Err(PushRequestError::ModuleUnavailable),
+            None => Err(PushRequestError::UnknownPushModule),
+        }
+    }
+
+    /// Secret a client has to send as `secret` publish-option.
+    /// Returns None if the push module does not require a secret
+    pub fn generate_secret(
+        &self,
+        module_id: &str,
+        token: &str,
+        origin_domain: &str,
+    ) -> PushRequestResult<Option<String>> {
+        match self.push_modules().get(module_id) {
+            Some(push_module) => Ok(push_module
+                .secret()
+                .map(|secret| secret.generate(token, origin_domain))),
+            None => Err(PushRequestError::UnknownPushModule),
+        }
+    }
+
+    /// Reply policy of the push module, the default policy for unknown push modules
+    pub fn reply_policy(&self, module_id: &str) -> Arc<ReplyPolicy> {
+        match self.push_modules().get(module_id) {
+            Some(push_module) => push_module.reply_policy().clone(),
+            None => Arc::new(ReplyPolicy::default()),
         }
     }
 
     #[inline(always)]
-    pub async fn push(&self, module_id: &str, token: String) -> PushRequestResult<()> {
-        if let Some(push_module) = self.push_modules.get(module_id) {
-            handle_push_request(push_module.value(), token).await
+    pub async fn push(&self, module_id: &str, request: &PushRequest) -> PushRequestResult<()> {
+        if let Some(push_module) = self.push_module(module_id) {
+            let result = handle_push_request(&push_module, request).await;
+            push_module.stats().record(&result);
+            result
+        } else if self.is_unavailable(module_id) {
+            debug!("Unavailable push_module requested: {}", module_id);
+            Err(PushRequestError::ModuleUnavailable)
         } else {
             debug!("Unknown push_module requested: {}", module_id);
             Err(PushRequestError::UnknownPushModule)
         }
     }
 }
+
+/// Ratelimit and blocklist state of a single token
+#[derive(Debug, Clone, Copy)]
+pub struct TokenStatus {
+    pub ratelimited: bool,
+    /// unix timestamp until which the token is blocked
+    pub blocked_until: Option<u64>,
+}
+
+#[cfg(all(test, feature = "enable_demo_support"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn default_and_aliases_share_module() {
+        let config: FpushPushConfig = serde_json::from_str(
+            r#"{
+                "demoProd": {"type": "demo", "is_default_module": true, "aliases": ["demoOld"]},
+                "demoDev": {"type": "demo"}
+            }"#,
+        )
+        .unwrap();
+        let push = FpushPush::new(&config, false).await.unwrap();
+
+        let module = push.push_module("demoProd").unwrap();
+        assert!(Arc::ptr_eq(&module, &push.push_module("default").unwrap()));
+        assert!(Arc::ptr_eq(&module, &push.push_module("demoOld").unwrap()));
+        assert!(!Arc::ptr_eq(&module, &push.push_module("demoDev").unwrap()));
+        assert_eq!(push.module_ids(), vec!["demoDev", "demoProd"]);
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_unchanged_state() {
+        let config: FpushPushConfig = serde_json::from_str(
+            r#"{
+                "demoProd": {"type": "demo", "is_default_module": true},
+                "demoDev": {"type": "demo"},
+                "demoOld": {"type": "demo"}
+            }"#,
+        )
+        .unwrap();
+        let push = FpushPush::new(&config, false).await.unwrap();
+        let prod = push.push_module("demoProd").unwrap();
+        let dev = push.push_module("demoDev").unwrap();
+
+        let config: FpushPushConfig = serde_json::from_str(
+            r#"{
+                "demoProd": {"type": "demo", "is_default_module": true},
+                "demoDev": {"type": "demo", "ratelimit": {"ratelimitTime": "1m"}},
+                "demoNew": {"type": "demo"}
+            }"#,
+        )
+        .unwrap();
+        push.reload(&config, false).await.unwrap();
+        assert_eq!(push.module_ids(), vec!["demoDev", "demoNew", "demoProd"]);
+
+        let reloaded_prod = push.push_module("default").unwrap();
+        assert!(!Arc::ptr_eq(&prod, &reloaded_prod));
+        assert!(Arc::ptr_eq(prod.ratelimit(), reloaded_prod.ratelimit()));
+        assert!(Arc::ptr_eq(prod.blocklist(), reloaded_prod.blocklist()));
+
+        let reloaded_dev = push.push_module("demoDev").unwrap();
+        assert!(!Arc::ptr_eq(dev.ratelimit(), reloaded_dev.ratelimit()));
+        assert!(Arc::ptr_eq(dev.blocklist(), reloaded_dev.blocklist()));
+    }
+
+    #[tokio::test]
+    async fn degraded_start_skips_failed_modules() {
+        let config: FpushPushConfig = serde_json::from_str(
+            r#"{
+                "demoA": {"type": "demo", "is_default_module": true},
+                "demoB": {"type": "demo", "is_default_module": true, "aliases": ["demoOld"]}
+            }"#,
+        )
+        .unwrap();
+        let e = FpushPush::new(&config, false).await.err().unwrap();
+        assert_eq!(e.failed_modules().len(), 1);
+        assert_eq!(e.failed_modules()[0].0, "demoB");
+
+        let push = FpushPush::new(&config, true).await.unwrap();
+        assert_eq!(push.module_ids(), vec!["demoA"]);
+        let failed_modules = push.failed_modules();
+        assert_eq!(failed_modules.len(), 1);
+        assert_eq!(failed_modules[0].0, "demoB");
+        let request = PushRequest::new(
+            "token".to_string(),
+            "example.org".to_string(),
+            PushSummary::default(),
+            HashMap::new(),
+        );
+        for module_id in ["demoB", "demoOld"] {
+            assert!(matches!(
+                push.push(module_id, &request).await,
+                Err(PushRequestError::ModuleUnavailable)
+            ));
+        }
+        assert!(matches!(
+            push.push("demoC", &request).await,
+            Err(PushRequestError::UnknownPushModule)
+        ));
+    }
+}
@@ -1,24 +1,98 @@
 use crate::error::{PushRequestError, PushRequestResult};
 
 use crate::push_module::PushModuleEnum;
-use fpush_traits::push::PushError;
+use crate::push_request::PushRequest;
+use fpush_traits::push::{PushError, PushResult};
 
 use log::{info, warn};
+use tokio::time::Instant;
+
+/// Reject requests without the valid `secret` publish-option if the push module requires one
+fn check_secret(push_module: &PushModuleEnum, request: &PushRequest) -> PushRequestResult<()> {
+    if let Some(secret) = push_module.secret() {
+        let authorized = request
+            .options()
+            .get("secret")
+            .is_some_and(|value| secret.verify(request.token(), request.origin_domain(), value));
+        if !authorized {
+            info!(
+                "{}: Rejecting request with missing or wrong secret for token {}",
+                push_module.identifier(),
+                request.token()
+            );
+            return Err(PushRequestError::NotAuthorized);
+        }
+    }
+    Ok(())
+}
+
+/// Forget the ratelimit state of a token after its node was deleted, purged or retracted
+/// and drop further push requests for it if configured
+pub(crate) fn handle_unregister_request(
+    push_module: &PushModuleEnum,
+    request: &PushRequest,
+) -> PushRequestResult<()> {
+    check_secret(push_module, request)?;
+    push_module.ratelimit().remove(request.token());
+    push_module.blocklist().block_unregistered(request.token());
+    info!(
+        "{}: Unregistered token {}",
+        push_module.identifier(),
+        request.token()
+    );
+    Ok(())
+}
 
 #[inline(always)]
 pub async fn handle_push_request(
     push_module: &PushModuleEnum,
-    token: String,
+    request: &PushRequest,
 ) -> PushRequestResult<()> {
-    if push_module.blocklist().is_blocked(&token) {
+    let token = request.token();
+    check_secret(push_module, request)?;
+    if push_module.blocklist().is_blocked(token) {
         return Err(PushRequestError::TokenBlocked);
     }
+    if push_module.blocklist().is_unregistered(token) {
+        info!(
+            "{}: Dropping push request for unregistered token {}",
+            push_module.identifier(),
+            token
+        );
+        return Err(PushRequestError::TokenUnregistered);
+    }
+    if !push_module.domain_limit().check(request.origin_domain()) {
+        info!(
+            "{}: Ignoring push request for token {} due to domain limit of {}",
+            push_module.identifier(),
+            token,
+            request.origin_domain(),
+        );
+        return Err(PushRequestError::DomainRatelimited);
+    }
+    #[cfg(feature = "random_delay_before_push")]
+    push_module.random_delay().wait().await;
     if push_module
         .ratelimit()
         .lookup_ratelimit(token.to_string())
         .await
     {
-        match push_module.send(token.to_string()).await {
+        // acquired right before sending, a half open probe slot is not held during the delay
+        let permit = match push_module.circuit_breaker().try_acquire() {
+            Some(permit) => permit,
+            None => {
+                info!(
+                    "{}: Rejecting push request for token {}, circuit breaker is open",
+                    push_module.identifier(),
+                    token
+                );
+                return Err(PushRequestError::CircuitOpen);
+            }
+        };
+        let result = send_with_retry(push_module, request).await;
+        // the breaker tracks requests, retries of a request count once
+        permit.record(&result);
+        match result {
             Ok(()) => {
                 info!(
                     "{}: Send push message to token {}",
@@ -33,7 +107,9 @@ pub async fn handle_push_request(
                     push_module.identifier(),
                     token,
                 );
-                push_module.blocklist().block_invalid_token(token);
+                push_module
+                    .blocklist()
+                    .block_invalid_token(token.to_string());
                 Err(PushRequestError::TokenBlocked)
             }
             Err(PushError::TokenRateLimited) => {
@@ -51,7 +127,7 @@ pub async fn handle_push_request(
                 );
                 push_module
                     .blocklist()
-                    .block_after_unhandled_push_error(token);
+                    .block_after_unhandled_push_error(token.to_string());
                 Err(PushRequestError::Internal)
             }
         }
@@ -64,3 +140,108 @@ pub async fn handle_push_request(
         Err(PushRequestError::TokenRatelimited)
     }
 }
+
+/// Send a push, retrying temporary backend errors according to the retry policy of the module
+async fn send_with_retry(push_module: &PushModuleEnum, request: &PushRequest) -> PushResult<()> {
+    let policy = push_module.retry_policy();
+    let deadline = Instant::now() + policy.deadline();
+    let mut attempt = 1;
+    loop {
+        let result = if attempt == 1 {
+            push_module
+                .send(request.token().to_string(), request.summary())
+                .await
+        } else {
+            tokio::time::timeout_at(
+                deadline,
+                push_module.send(request.token().to_string(), request.summary()),
+            )
+            .await
+            .unwrap_or(Err(PushError::PushEndpointTmp))
+        };
+        match result {
+            // no retries while the backend is considered unavailable
+            Err(PushError::PushEndpointTmp)
+                if attempt < policy.max_attempts() && push_module.circuit_breaker().is_closed() =>
+            {
+                let delay = policy.delay(attempt);
+                if Instant::now() + delay >= deadline {
+                    return result;
+                }
+                push_module.stats().record_retry();
+                warn!(
+                    "{}: Retrying push for token {} after temporary backend error in {}ms (retry {}/{})",
+                    push_module.identifier(),
+                    request.token(),
+                    delay.as_millis(),
+                    attempt,
+                    policy.max_attempts() - 1
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "enable_demo_support"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::fpush_config::PushModuleSettings;
+    use crate::push_module::{PushModule, PushModuleState};
+    use fpush_traits::push::PushSummary;
+
+    fn unregister_request(secret: Option<&str>) -> PushRequest {
+        let mut options = HashMap::new();
+        if let Some(secret) = secret {
+            options.insert("secret".to_string(), secret.to_string());
+        }
+        PushRequest::new(
+            "token".to_string(),
+            "example.org".to_string(),
+            PushSummary::default(),
+            options,
+        )
+    }
+
+    #[tokio::test]
+    async fn unregister_requires_secret() {
+        let settings: PushModuleSettings = serde_json::from_str(
+            r#"{
+                "secret": {"key": "server key"},
+                "blacklist": {
+                    "invalidToken": {"initalBlocking": "1d", "extendedBlocking": "5d"},
+                    "pushError": {"initalBlocking": "10m", "extendedBlocking": "20m"},
+                    "blockExtension": "10m",
+                    "unregisteredBlock": "1h"
+                }
+            }"#,
+        )
+        .unwrap();
+        let push_module = PushModuleEnum::Demo(
+            PushModule::new_demo_module("demo".to_string(), &settings, PushModuleState::default())
+                .await
+                .unwrap(),
+        );
+
+        for secret in [None, Some("00"), Some("not hex")] {
+            assert!(matches!(
+                handle_unregister_request(&push_module, &unregister_request(secret)),
+                Err(PushRequestError::NotAuthorized)
+            ));
+            assert!(!push_module.blocklist().is_unregistered("token"));
+        }
+
+        let secret = push_module
+            .secret()
+            .unwrap()
+            .generate("token", "example.org");
+        assert!(
+            handle_unregister_request(&push_module, &unregister_request(Some(&secret))).is_ok()
+        );
+        assert!(push_module.blocklist().is_unregistered("token"));
+    }
+}
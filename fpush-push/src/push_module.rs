@@ -1,16 +1,27 @@
 use std::sync::Arc;
 
+use crate::circuit_breaker::CircuitBreaker;
 use crate::error::Result;
-use fpush_ratelimit::{FpushTokenRateLimit, RatelimitSettings};
-use fpush_tokenblocker::BlacklistSettings;
-use fpush_tokenblocker::FpushBlocklist;
+use crate::fpush_config::PushModuleSettings;
+#[cfg(feature = "random_delay_before_push")]
+use crate::random_delay::RandomDelay;
+use crate::reply_policy::ReplyPolicy;
+use crate::retry::RetryPolicy;
+use crate::secret::PushSecret;
+use crate::stats::PushModuleStats;
+use fpush_ratelimit::{
+    DomainLimitSettings, FpushDomainLimit, FpushTokenRateLimit, RatelimitSettings,
+};
+use fpush_tokenblocker::{BlacklistSettings, FpushBlocklist};
 
-use fpush_traits::push::PushResult;
+use fpush_traits::push::{PushResult, PushSummary};
 
 use dashmap::DashMap;
 use fpush_traits::push::PushTrait;
+use tokio::task::AbortHandle;
 
-pub type PushModuleMapArc = Arc<DashMap<String, PushModuleEnum>>;
+/// Push modules by identifier, the "default" module and aliases share the instance
+pub type PushModuleMapArc = Arc<DashMap<String, Arc<PushModuleEnum>>>;
 
 pub enum PushModuleEnum {
     #[cfg(feature = "enable_apns_support")]
@@ -24,14 +35,14 @@ pub enum PushModuleEnum {
 impl PushModuleEnum {
     /// dispatch
     #[inline(always)]
-    pub async fn send(&self, token: String) -> PushResult<()> {
+    pub async fn send(&self, token: String, summary: &PushSummary) -> PushResult<()> {
         match self {
             #[cfg(feature = "enable_apns_support")]
-            PushModuleEnum::Apple(push_module) => push_module.send(token).await,
+            PushModuleEnum::Apple(push_module) => push_module.send(token, summary).await,
             #[cfg(feature = "enable_fcm_support")]
-            PushModuleEnum::Google(push_module) => push_module.send(token).await,
+            PushModuleEnum::Google(push_module) => push_module.send(token, summary).await,
             #[cfg(feature = "enable_demo_support")]
-            PushModuleEnum::Demo(push_module) => push_module.send(token).await,
+            PushModuleEnum::Demo(push_module) => push_module.send(token, summary).await,
         }
     }
 
@@ -47,6 +58,18 @@ impl PushModuleEnum {
         }
     }
 
+    #[inline(always)]
+    pub fn domain_limit(&self) -> &Arc<FpushDomainLimit> {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.domain_limit(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.domain_limit(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.domain_limit(),
+        }
+    }
+
     #[inline(always)]
     pub fn ratelimit(&self) -> &Arc<FpushTokenRateLimit> {
         match self {
@@ -70,6 +93,118 @@ impl PushModuleEnum {
             PushModuleEnum::Demo(push_module) => push_module.identifier(),
         }
     }
+
+    #[inline(always)]
+    pub(crate) fn secret(&self) -> Option<&PushSecret> {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.secret(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.secret(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.secret(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn reply_policy(&self) -> &Arc<ReplyPolicy> {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.reply_policy(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.reply_policy(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.reply_policy(),
+        }
+    }
+
+    #[cfg(feature = "random_delay_before_push")]
+    #[inline(always)]
+    pub(crate) fn random_delay(&self) -> &RandomDelay {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.random_delay(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.random_delay(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.random_delay(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn circuit_breaker(&self) -> &CircuitBreaker {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.circuit_breaker(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.circuit_breaker(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.circuit_breaker(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn retry_policy(&self) -> &RetryPolicy {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.retry_policy(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.retry_policy(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.retry_policy(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn stats(&self) -> &PushModuleStats {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.stats(),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.stats(),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.stats(),
+        }
+    }
+
+    /// State the reloaded instance of this push module can keep with the new settings
+    pub(crate) fn reusable_state(&self, settings: &PushModuleSettings) -> PushModuleState {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(push_module) => push_module.reusable_state(settings),
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(push_module) => push_module.reusable_state(settings),
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(push_module) => push_module.reusable_state(settings),
+        }
+    }
+
+    /// type of the push module as used in the configuration
+    pub fn module_type(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "enable_apns_support")]
+            PushModuleEnum::Apple(_) => "apple",
+            #[cfg(feature = "enable_fcm_support")]
+            PushModuleEnum::Google(_) => "google",
+            #[cfg(feature = "enable_demo_support")]
+            PushModuleEnum::Demo(_) => "demo",
+        }
+    }
+}
+
+/// Token state of a push module handed to its reloaded instance.
+/// Each part is only kept if its settings did not change
+#[derive(Default)]
+pub(crate) struct PushModuleState {
+    blocklist: Option<Arc<FpushBlocklist>>,
+    token_ratelimit: Option<Arc<FpushTokenRateLimit>>,
+    domain_limit: Option<Arc<FpushDomainLimit>>,
+}
+
+impl PushModuleState {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.blocklist.is_none() && self.token_ratelimit.is_none() && self.domain_limit.is_none()
+    }
 }
 
 pub struct PushModule<T>
@@ -78,8 +213,21 @@ where
 {
     blocklist: Arc<FpushBlocklist>,
     token_ratelimit: Arc<FpushTokenRateLimit>,
+    domain_limit: Arc<FpushDomainLimit>,
+    blacklist_settings: BlacklistSettings,
+    ratelimit_settings: RatelimitSettings,
+    domain_limit_settings: DomainLimitSettings,
+    /// cleanup tasks of the state maps, aborted once the push module is dropped
+    cleanup_tasks: Vec<AbortHandle>,
     push: Arc<T>,
     identifier: String,
+    stats: PushModuleStats,
+    secret: Option<PushSecret>,
+    reply_policy: Arc<ReplyPolicy>,
+    retry_policy: RetryPolicy,
+    circuit_breaker: CircuitBreaker,
+    #[cfg(feature = "random_delay_before_push")]
+    random_delay: RandomDelay,
 }
 
 #[cfg(feature = "enable_apns_support")]
@@ -87,16 +235,11 @@ impl PushModule<fpush_apns::FpushApns> {
     pub(crate) fn new_apple_module(
         identifier: String,
         apns_conf: &fpush_apns::AppleApnsConfig,
-        blocklist_config: &BlacklistSettings,
-        ratelimit_config: &RatelimitSettings,
+        settings: &PushModuleSettings,
+        state: PushModuleState,
     ) -> Result<PushModule<fpush_apns::FpushApns>> {
         let apple_push = fpush_apns::FpushApns::init(apns_conf)?;
-        Self::new(
-            identifier,
-            blocklist_config,
-            ratelimit_config,
-            Arc::new(apple_push),
-        )
+        Self::new(identifier, settings, Arc::new(apple_push), state)
     }
 }
 
@@ -105,16 +248,11 @@ impl PushModule<fpush_fcm::FpushFcm> {
     pub(crate) async fn new_fcm_module(
         identifier: String,
         fcm_conf: &fpush_fcm::GoogleFcmConfig,
-        blocklist_config: &BlacklistSettings,
-        ratelimit_config: &RatelimitSettings,
+        settings: &PushModuleSettings,
+        state: PushModuleState,
     ) -> Result<PushModule<fpush_fcm::FpushFcm>> {
         let fcm_push = fpush_fcm::FpushFcm::init(fcm_conf).await?;
-        Self::new(
-            identifier,
-            blocklist_config,
-            ratelimit_config,
-            Arc::new(fcm_push),
-        )
+        Self::new(identifier, settings, Arc::new(fcm_push), state)
     }
 }
 
@@ -122,16 +260,11 @@ impl PushModule<fpush_fcm::FpushFcm> {
 impl PushModule<fpush_demopush::FpushDemoPush> {
     pub(crate) async fn new_demo_module(
         identifier: String,
-        blocklist_config: &BlacklistSettings,
-        ratelimit_config: &RatelimitSettings,
+        settings: &PushModuleSettings,
+        state: PushModuleState,
     ) -> Result<PushModule<fpush_demopush::FpushDemoPush>> {
         let demo_module = fpush_demopush::FpushDemoPush::init()?;
-        Self::new(
-            identifier,
-            blocklist_config,
-            ratelimit_config,
-            Arc::new(demo_module),
-        )
+        Self::new(identifier, settings, Arc::new(demo_module), state)
     }
 }
 
@@ -139,55 +272,102 @@ impl<T> PushModule<T>
 where
     T: PushTrait,
 {
-    /// Create new push module of type <T>
+    /// Create new push module of type <T>, keeping the token state of a previous instance
     pub(crate) fn new(
         identifier: String,
-        blocklist_config: &BlacklistSettings,
-        ratelimit_config: &RatelimitSettings,
+        settings: &PushModuleSettings,
         push: Arc<T>,
+        state: PushModuleState,
     ) -> Result<Self> {
-        let blocklist = fpush_tokenblocker::FpushBlocklist::new(blocklist_config);
+        let blocklist = state
+            .blocklist
+            .unwrap_or_else(|| Arc::new(FpushBlocklist::new(settings.blacklist())));
+        let token_ratelimit = state
+            .token_ratelimit
+            .unwrap_or_else(|| Arc::new(FpushTokenRateLimit::new(settings.ratelimit())));
+        let domain_limit = state
+            .domain_limit
+            .unwrap_or_else(|| Arc::new(FpushDomainLimit::new(settings.domain_limit())));
 
-        let token_ratelimit = fpush_ratelimit::FpushTokenRateLimit::new(ratelimit_config);
+        let circuit_breaker = CircuitBreaker::new(&identifier, settings.circuit_breaker());
 
-        let module = Self {
-            blocklist: Arc::new(blocklist),
-            token_ratelimit: Arc::new(token_ratelimit),
+        let mut module = Self {
+            blocklist,
+            token_ratelimit,
+            domain_limit,
+            blacklist_settings: *settings.blacklist(),
+            ratelimit_settings: settings.ratelimit().clone(),
+            domain_limit_settings: settings.domain_limit().clone(),
+            cleanup_tasks: Vec::with_capacity(3),
             push,
             identifier,
+            stats: PushModuleStats::default(),
+            secret: settings.secret().as_ref().map(PushSecret::new),
+            reply_policy: Arc::new(settings.reply_policy().clone()),
+            retry_policy: RetryPolicy::new(settings.retry()),
+            circuit_breaker,
+            #[cfg(feature = "random_delay_before_push")]
+            random_delay: RandomDelay::new(settings.random_delay()),
         };
         module.spawn_blocklist_cleanup();
         module.spawn_token_cleanup();
+        module.spawn_domain_cleanup();
 
         Ok(module)
     }
 
+    /// Share the state maps whose settings are unchanged in `settings`
+    fn reusable_state(&self, settings: &PushModuleSettings) -> PushModuleState {
+        PushModuleState {
+            blocklist: (self.blacklist_settings == *settings.blacklist())
+                .then(|| self.blocklist.clone()),
+            token_ratelimit: (self.ratelimit_settings == *settings.ratelimit())
+                .then(|| self.token_ratelimit.clone()),
+            domain_limit: (self.domain_limit_settings == *settings.domain_limit())
+                .then(|| self.domain_limit.clone()),
+        }
+    }
+
     /// trigger push event got provided token
     #[inline(always)]
-    async fn send(&self, token: String) -> PushResult<()> {
-        self.push.send(token).await
+    async fn send(&self, token: String, summary: &PushSummary) -> PushResult<()> {
+        self.push.send(token, summary).await
     }
 
-    fn spawn_blocklist_cleanup(&self) {
+    fn spawn_blocklist_cleanup(&mut self) {
         let blocklist = self.blocklist.clone();
-        tokio::spawn(async move {
+        let task = tokio::spawn(async move {
             let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
             loop {
                 interval.tick().await;
                 blocklist.cleanup();
             }
         });
+        self.cleanup_tasks.push(task.abort_handle());
     }
 
-    fn spawn_token_cleanup(&self) {
+    fn spawn_token_cleanup(&mut self) {
         let token_ratelimit = self.token_ratelimit.clone();
-        tokio::spawn(async move {
+        let task = tokio::spawn(async move {
             let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(300));
             loop {
                 interval.tick().await;
                 token_ratelimit.cleanup();
             }
         });
+        self.cleanup_tasks.push(task.abort_handle());
+    }
+
+    fn spawn_domain_cleanup(&mut self) {
+        let domain_limit = self.domain_limit.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(300));
+            loop {
+                interval.tick().await;
+                domain_limit.cleanup();
+            }
+        });
+        self.cleanup_tasks.push(task.abort_handle());
     }
 
     #[inline(always)]
@@ -200,8 +380,55 @@ where
         &self.token_ratelimit
     }
 
+    #[inline(always)]
+    pub fn domain_limit(&self) -> &Arc<FpushDomainLimit> {
+        &self.domain_limit
+    }
+
     #[inline(always)]
     pub fn identifier(&self) -> &str {
         &self.identifier
     }
+
+    #[inline(always)]
+    pub(crate) fn stats(&self) -> &PushModuleStats {
+        &self.stats
+    }
+
+    #[inline(always)]
+    pub(crate) fn secret(&self) -> Option<&PushSecret> {
+        self.secret.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn reply_policy(&self) -> &Arc<ReplyPolicy> {
+        &self.reply_policy
+    }
+
+    #[inline(always)]
+    pub(crate) fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    #[inline(always)]
+    pub(crate) fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    #[cfg(feature = "random_delay_before_push")]
+    #[inline(always)]
+    pub(crate) fn random_delay(&self) -> &RandomDelay {
+        &self.random_delay
+    }
+}
+
+impl<T> Drop for PushModule<T>
+where
+    T: PushTrait,
+{
+    fn drop(&mut self) {
+        for task in &self.cleanup_tasks {
+            task.abort();
+        }
+    }
 }
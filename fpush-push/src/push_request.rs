@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+use fpush_traits::push::PushSummary;
+
+use derive_getters::Getters;
+
+/// A single push request as received from the XMPP server
+#[derive(Debug, Clone, Getters)]
+pub struct PushRequest {
+    token: String,
+    /// domain of the XMPP server that sent the request
+    origin_domain: String,
+    summary: PushSummary,
+    /// publish-options fields not interpreted by fpush itself, e.g. `secret`
+    options: HashMap<String, String>,
+}
+
+impl PushRequest {
+    pub fn new(
+        token: String,
+        origin_domain: String,
+        summary: PushSummary,
+        options: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            token,
+            origin_domain,
+            summary,
+            options,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use crate::fpush_config::RandomDelaySettings;
+
+use rand::Rng;
+
+/// Random delay awaited before the ratelimit check of each push request.
+/// Spreads consecutive requests for the same token so the ratelimit sees them in order.
+pub(crate) struct RandomDelay {
+    min: Duration,
+    max: Duration,
+}
+
+impl RandomDelay {
+    pub(crate) fn new(settings: &RandomDelaySettings) -> Self {
+        Self {
+            min: *settings.min(),
+            max: (*settings.max()).max(*settings.min()),
+        }
+    }
+
+    fn sample(&self) -> Duration {
+        rand::thread_rng().gen_range(self.min..=self.max)
+    }
+
+    pub(crate) async fn wait(&self) {
+        let delay = self.sample();
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_within_range() {
+        let settings: RandomDelaySettings =
+            serde_json::from_str(r#"{"min": "20ms", "max": "5ms"}"#).unwrap();
+        let delay = RandomDelay::new(&settings);
+        for _ in 0..100 {
+            assert_eq!(delay.sample(), Duration::from_millis(20));
+        }
+
+        let settings: RandomDelaySettings =
+            serde_json::from_str(r#"{"min": "5ms", "max": "20ms"}"#).unwrap();
+        let delay = RandomDelay::new(&settings);
+        for _ in 0..100 {
+            let sample = delay.sample();
+            assert!(sample >= Duration::from_millis(5) && sample <= Duration::from_millis(20));
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::PushRequestError;
+
+use derive_getters::Getters;
+use serde::Deserialize;
+
+/// XMPP stanza error type of a reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyErrorType {
+    Auth,
+    Cancel,
+    Continue,
+    Modify,
+    Wait,
+}
+
+/// XMPP stanza error condition of a reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplyErrorCondition {
+    BadRequest,
+    Conflict,
+    FeatureNotImplemented,
+    Forbidden,
+    InternalServerError,
+    ItemNotFound,
+    NotAcceptable,
+    NotAllowed,
+    NotAuthorized,
+    PolicyViolation,
+    ResourceConstraint,
+    ServiceUnavailable,
+    UndefinedCondition,
+    UnexpectedRequest,
+}
+
+/// Reply to a push request that did not result in a push
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "reply", rename_all = "camelCase")]
+pub enum ReplyAction {
+    /// acknowledge the request as if the push was sent
+    Ack,
+    Error {
+        #[serde(rename = "type")]
+        error_type: ReplyErrorType,
+        condition: ReplyErrorCondition,
+        /// defaults to a description of the outcome
+        #[serde(default)]
+        text: Option<String>,
+    },
+}
+
+impl ReplyAction {
+    fn error(error_type: ReplyErrorType, condition: ReplyErrorCondition) -> Self {
+        ReplyAction::Error {
+            error_type,
+            condition,
+            text: None,
+        }
+    }
+}
+
+/// Reply for each failed push request outcome of a push module
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplyPolicy {
+    token_ratelimited: ReplyAction,
+    domain_ratelimited: ReplyAction,
+    token_blocked: ReplyAction,
+    token_unregistered: ReplyAction,
+    internal: ReplyAction,
+    not_authorized: ReplyAction,
+    circuit_open: ReplyAction,
+    /// add an fpush namespaced element naming the outcome to error replies, disabled by default
+    application_errors: bool,
+}
+
+impl Default for ReplyPolicy {
+    fn default() -> Self {
+        Self {
+            // some server admins did not understand wait errors, hence ratelimited requests are acked
+            token_ratelimited: ReplyAction::Ack,
+            domain_ratelimited: ReplyAction::error(
+                ReplyErrorType::Wait,
+                ReplyErrorCondition::PolicyViolation,
+            ),
+            token_blocked: ReplyAction::error(
+                ReplyErrorType::Cancel,
+                ReplyErrorCondition::PolicyViolation,
+            ),
+            // late pushes after a disable are dropped silently
+            token_unregistered: ReplyAction::Ack,
+            internal: ReplyAction::error(ReplyErrorType::Cancel, ReplyErrorCondition::BadRequest),
+            not_authorized: ReplyAction::error(
+                ReplyErrorType::Auth,
+                ReplyErrorCondition::NotAuthorized,
+            ),
+            circuit_open: ReplyAction::error(
+                ReplyErrorType::Wait,
+                ReplyErrorCondition::ServiceUnavailable,
+            ),
+            application_errors: false,
+        }
+    }
+}
+
+impl ReplyPolicy {
+    /// Reply configured for the outcome of a push request
+    pub fn action(&self, error: &PushRequestError) -> ReplyAction {
+        match error {
+            PushRequestError::TokenRatelimited => self.token_ratelimited.clone(),
+            PushRequestError::DomainRatelimited => self.domain_ratelimited.clone(),
+            PushRequestError::TokenBlocked => self.token_blocked.clone(),
+            PushRequestError::TokenUnregistered => self.token_unregistered.clone(),
+            PushRequestError::Internal => self.internal.clone(),
+            PushRequestError::NotAuthorized => self.not_authorized.clone(),
+            PushRequestError::CircuitOpen => self.circuit_open.clone(),
+            // no push module, hence no configurable policy
+            PushRequestError::UnknownPushModule => {
+                ReplyAction::error(ReplyErrorType::Cancel, ReplyErrorCondition::BadRequest)
+            }
+            PushRequestError::ModuleUnavailable => ReplyAction::error(
+                ReplyErrorType::Cancel,
+                ReplyErrorCondition::ServiceUnavailable,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplyAction, ReplyErrorCondition, ReplyErrorType, ReplyPolicy};
+    use crate::PushRequestError;
+
+    #[test]
+    fn partial_policy_keeps_defaults() {
+        let policy: ReplyPolicy = serde_json::from_value(serde_json::json!({
+            "tokenRatelimited": {
+                "reply": "error",
+                "type": "wait",
+                "condition": "resource-constraint",
+                "text": "Slow down"
+            },
+            "tokenBlocked": { "reply": "ack" }
+        }))
+        .unwrap();
+        assert_eq!(
+            policy.action(&PushRequestError::TokenRatelimited),
+            ReplyAction::Error {
+                error_type: ReplyErrorType::Wait,
+                condition: ReplyErrorCondition::ResourceConstraint,
+                text: Some("Slow down".to_string()),
+            }
+        );
+        assert_eq!(
+            policy.action(&PushRequestError::TokenBlocked),
+            ReplyAction::Ack
+        );
+        assert_eq!(
+            policy.action(&PushRequestError::Internal),
+            ReplyPolicy::default().action(&PushRequestError::Internal)
+        );
+        assert!(!*policy.application_errors());
+    }
+
+    #[test]
+    fn rejects_unknown_condition() {
+        let policy = serde_json::from_value::<ReplyPolicy>(serde_json::json!({
+            "internal": { "reply": "error", "type": "cancel", "condition": "an-error-occured" }
+        }));
+        assert!(policy.is_err());
+    }
+}
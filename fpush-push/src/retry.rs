@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use crate::fpush_config::RetrySettings;
+
+/// Bounded exponential backoff for push attempts that failed with a temporary backend error
+pub(crate) struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    deadline: Duration,
+}
+
+impl RetryPolicy {
+    pub(crate) fn new(settings: &RetrySettings) -> Self {
+        Self {
+            max_attempts: (*settings.max_attempts()).max(1),
+            initial_delay: *settings.initial_delay(),
+            multiplier: settings.multiplier().max(1.0),
+            max_delay: *settings.max_delay(),
+            deadline: *settings.deadline(),
+        }
+    }
+
+    /// Delay before the given retry, starting with 1 for the first retry
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let exponent = i32::try_from(retry.saturating_sub(1)).unwrap_or(i32::MAX);
+        let factor = self.multiplier.powi(exponent);
+        Duration::try_from_secs_f64(self.initial_delay.as_secs_f64() * factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    #[inline(always)]
+    pub(crate) fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Time after the first attempt after which no further attempt is started
+    #[inline(always)]
+    pub(crate) fn deadline(&self) -> Duration {
+        self.deadline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_until_max_delay() {
+        let settings: RetrySettings = serde_json::from_str(
+            r#"{"initialDelay": "100ms", "multiplier": 2.0, "maxDelay": "1s"}"#,
+        )
+        .unwrap();
+        let policy = RetryPolicy::new(&settings);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::fpush_config::PushSecretSettings;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HMAC-SHA256 over the token (and optionally the origin domain) used as XEP-0357 `secret`
+pub(crate) struct PushSecret {
+    key: Vec<u8>,
+    include_origin_domain: bool,
+}
+
+impl PushSecret {
+    pub(crate) fn new(settings: &PushSecretSettings) -> Self {
+        Self {
+            key: settings.key().as_bytes().to_vec(),
+            include_origin_domain: *settings.include_origin_domain(),
+        }
+    }
+
+    fn mac(&self, token: &str, origin_domain: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(token.as_bytes());
+        if self.include_origin_domain {
+            mac.update(b"\n");
+            mac.update(origin_domain.as_bytes());
+        }
+        mac
+    }
+
+    /// Hex encoded secret a client has to send with its push requests
+    pub(crate) fn generate(&self, token: &str, origin_domain: &str) -> String {
+        hex::encode(self.mac(token, origin_domain).finalize().into_bytes())
+    }
+
+    /// Check a hex encoded secret in constant time
+    pub(crate) fn verify(&self, token: &str, origin_domain: &str, secret: &str) -> bool {
+        match hex::decode(secret) {
+            Ok(secret) => self.mac(token, origin_domain).verify_slice(&secret).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PushSecret;
+
+    fn push_secret(include_origin_domain: bool) -> PushSecret {
+        PushSecret {
+            key: b"server-key".to_vec(),
+            include_origin_domain,
+        }
+    }
+
+    #[test]
+    fn verify_generated_secret() {
+        let secret = push_secret(false);
+        let value = secret.generate("some-token", "example.org");
+        assert!(secret.verify("some-token", "example.org", &value));
+        assert!(secret.verify("some-token", "example.org", &value.to_uppercase()));
+        assert!(!secret.verify("other-token", "example.org", &value));
+        assert!(!secret.verify("some-token", "example.org", "not-hex"));
+        assert!(!secret.verify("some-token", "example.org", ""));
+        // origin domain is ignored unless configured
+        assert!(secret.verify("some-token", "example.com", &value));
+    }
+
+    #[test]
+    fn origin_domain_is_bound() {
+        let secret = push_secret(true);
+        let value = secret.generate("some-token", "example.org");
+        assert!(secret.verify("some-token", "example.org", &value));
+        assert!(!secret.verify("some-token", "example.com", &value));
+        assert_ne!(
+            value,
+            push_secret(false).generate("some-token", "example.org")
+        );
+    }
+}
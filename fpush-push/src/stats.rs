@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::{PushRequestError, PushRequestResult};
+
+use derive_getters::Getters;
+
+/// Counters of a single push module
+#[derive(Default)]
+pub(crate) struct PushModuleStats {
+    requests: AtomicU64,
+    delivered: AtomicU64,
+    ratelimited: AtomicU64,
+    domain_ratelimited: AtomicU64,
+    blocked: AtomicU64,
+    unregistered: AtomicU64,
+    not_authorized: AtomicU64,
+    circuit_open: AtomicU64,
+    errors: AtomicU64,
+    consecutive_errors: AtomicU64,
+    /// retried push attempts after temporary backend errors
+    retries: AtomicU64,
+}
+
+impl PushModuleStats {
+    #[inline(always)]
+    pub(crate) fn record(&self, result: &PushRequestResult<()>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(()) => {
+                self.delivered.fetch_add(1, Ordering::Relaxed);
+                self.consecutive_errors.store(0, Ordering::Relaxed);
+            }
+            Err(PushRequestError::TokenRatelimited) => {
+                self.ratelimited.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(PushRequestError::DomainRatelimited) => {
+                self.domain_ratelimited.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(PushRequestError::TokenBlocked) => {
+                self.blocked.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(PushRequestError::TokenUnregistered) => {
+                self.unregistered.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(PushRequestError::NotAuthorized) => {
+                self.not_authorized.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(PushRequestError::CircuitOpen) => {
+                self.circuit_open.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                self.consecutive_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> PushModuleStatsSnapshot {
+        PushModuleStatsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            ratelimited: self.ratelimited.load(Ordering::Relaxed),
+            domain_ratelimited: self.domain_ratelimited.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+            unregistered: self.unregistered.load(Ordering::Relaxed),
+            not_authorized: self.not_authorized.load(Ordering::Relaxed),
+            circuit_open: self.circuit_open.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            consecutive_errors: self.consecutive_errors.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Point in time copy of the counters of a push module
+#[derive(Debug, Clone, Copy, Getters)]
+pub struct PushModuleStatsSnapshot {
+    requests: u64,
+    delivered: u64,
+    ratelimited: u64,
+    domain_ratelimited: u64,
+    blocked: u64,
+    unregistered: u64,
+    not_authorized: u64,
+    circuit_open: u64,
+    errors: u64,
+    consecutive_errors: u64,
+    retries: u64,
+}
+
+impl PushModuleStatsSnapshot {
+    /// A module is considered failing if its last push request ended with an internal error
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_errors == 0
+    }
+}
+
+impl std::fmt::Display for PushModuleStatsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "requests={} delivered={} ratelimited={} domain_ratelimited={} blocked={} unregistered={} not_authorized={} circuit_open={} errors={} consecutive_errors={} retries={}",
+            self.requests,
+            self.delivered,
+            self.ratelimited,
+            self.domain_ratelimited,
+            self.blocked,
+            self.unregistered,
+            self.not_authorized,
+            self.circuit_open,
+            self.errors,
+            self.consecutive_errors,
+            self.retries,
+        )
+    }
+}
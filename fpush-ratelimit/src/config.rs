@@ -1,7 +1,7 @@
 use serde::Deserialize;
 use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase", default)]
 pub struct RatelimitSettings {
     #[serde(deserialize_with = "serde_humantime")]
@@ -42,6 +42,39 @@ impl RatelimitSettings {
     }
 }
 
+/// Token bucket per origin domain: `burst` pushes at once, refilled with `rate` pushes per second
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DomainLimitSettings {
+    pub rate: f64,
+    pub burst: u32,
+    pub enabled: bool,
+}
+
+impl Default for DomainLimitSettings {
+    fn default() -> Self {
+        Self {
+            rate: 20.0,
+            burst: 500,
+            enabled: false,
+        }
+    }
+}
+
+impl DomainLimitSettings {
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
 pub fn serde_humantime<'de, D>(deserializer: D) -> std::result::Result<Duration, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -1 +1,155 @@
+use dashmap::DashMap;
+use log::debug;
+use std::time::Instant;
 
+/// Token bucket ratelimit per origin domain, limits the pushes a single XMPP server can trigger
+pub struct FpushDomainLimit {
+    bucket_map: DashMap<String, DomainBucket>,
+    rate: f64,
+    burst: f64,
+    enabled: bool,
+}
+
+struct DomainBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl DomainBucket {
+    #[inline(always)]
+    pub(crate) fn new(burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            last_refill: now,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.last_refill = now;
+    }
+
+    #[inline(always)]
+    pub(crate) fn try_take(&mut self) -> bool {
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl FpushDomainLimit {
+    pub fn new(config: &crate::DomainLimitSettings) -> Self {
+        Self {
+            bucket_map: DashMap::new(),
+            rate: config.rate(),
+            burst: f64::from(config.burst().max(1)),
+            enabled: config.is_enabled(),
+        }
+    }
+
+    /// Take one push from the budget of the domain.
+    /// Returns false if the domain exceeded its rate and burst
+    #[inline(always)]
+    pub fn check(&self, domain: &str) -> bool {
+        self.check_at(domain, Instant::now())
+    }
+
+    #[inline(always)]
+    fn check_at(&self, domain: &str, now: Instant) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let mut bucket = self
+            .bucket_map
+            .entry(domain.to_string())
+            .or_insert_with(|| DomainBucket::new(self.burst, now));
+        bucket.refill(self.rate, self.burst, now);
+        let allowed = bucket.try_take();
+        if !allowed {
+            debug!("Domain limit exceeded for {}", domain);
+        }
+        allowed
+    }
+
+    /// Remove domains that did not push for long enough to refill their whole burst
+    pub fn cleanup(&self) {
+        self.cleanup_at(Instant::now());
+    }
+
+    fn cleanup_at(&self, now: Instant) {
+        self.bucket_map.retain(|_, bucket| {
+            bucket.refill(self.rate, self.burst, now);
+            bucket.tokens < self.burst
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DomainLimitSettings;
+    use crate::FpushDomainLimit;
+
+    use std::time::{Duration, Instant};
+
+    fn domain_limit(rate: f64, burst: u32) -> FpushDomainLimit {
+        FpushDomainLimit::new(&DomainLimitSettings {
+            rate,
+            burst,
+            enabled: true,
+        })
+    }
+
+    #[test]
+    fn disabled() {
+        let dl = FpushDomainLimit::new(&DomainLimitSettings {
+            burst: 1,
+            ..Default::default()
+        });
+        for _i in 0..10 {
+            assert!(dl.check("example.org"));
+        }
+    }
+
+    #[test]
+    fn burst() {
+        let dl = domain_limit(0.001, 5);
+        for _i in 0..5 {
+            assert!(dl.check("example.org"));
+        }
+        assert!(!dl.check("example.org"));
+        // other domains have their own budget
+        assert!(dl.check("example.com"));
+    }
+
+    #[test]
+    fn refill() {
+        let dl = domain_limit(20.0, 2);
+        let start = Instant::now();
+        assert!(dl.check_at("example.org", start));
+        assert!(dl.check_at("example.org", start));
+        assert!(!dl.check_at("example.org", start));
+        let later = start + Duration::from_millis(50);
+        assert!(dl.check_at("example.org", later));
+        assert!(!dl.check_at("example.org", later));
+        let much_later = start + Duration::from_secs(10);
+        assert!(dl.check_at("example.org", much_later));
+        assert!(dl.check_at("example.org", much_later));
+        assert!(!dl.check_at("example.org", much_later));
+    }
+
+    #[test]
+    fn cleanup() {
+        let dl = domain_limit(20.0, 2);
+        let start = Instant::now();
+        assert!(dl.check_at("example.org", start));
+        dl.cleanup_at(start + Duration::from_millis(25));
+        assert_eq!(dl.bucket_map.len(), 1);
+        dl.cleanup_at(start + Duration::from_millis(100));
+        assert!(dl.bucket_map.is_empty());
+    }
+}
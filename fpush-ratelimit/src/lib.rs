@@ -1,7 +1,8 @@
 mod domain_limit;
+pub use domain_limit::FpushDomainLimit;
 
 mod token_ratelimit;
 pub use token_ratelimit::FpushTokenRateLimit;
 
 mod config;
-pub use config::RatelimitSettings;
+pub use config::{DomainLimitSettings, RatelimitSettings};
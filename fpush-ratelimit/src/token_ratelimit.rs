@@ -122,6 +122,21 @@ impl FpushTokenRateLimit {
         }
     }
 
+    /// Check if a push for the token would currently be delayed or dropped, without updating the ratelimit
+    pub fn is_ratelimited(&self, token: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.ratelimit_map
+            .get(token)
+            .is_some_and(|entry| entry.time_since_last_push() < self.time_between_pushes)
+    }
+
+    /// Forget the ratelimit state of an unregistered token. Returns true if the token was known
+    pub fn remove(&self, token: &str) -> bool {
+        self.ratelimit_map.remove(token).is_some()
+    }
+
     #[inline(always)]
     pub fn hard_ratelimit(&self, token: String) {
         debug!("Adding hard rate limit for token {}", token);
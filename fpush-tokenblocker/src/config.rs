@@ -1,13 +1,16 @@
 use serde::Deserialize;
 use std::time::Duration;
 
-#[derive(Debug, Deserialize, Copy, Clone)]
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct BlacklistSettings {
     invalid_token: BlacklistBlockingTimes,
     push_error: BlacklistBlockingTimes,
     #[serde(deserialize_with = "serde_humantime")]
     block_extension: Duration,
+    /// time pushes are dropped after a token was unregistered, zero disables it
+    #[serde(default, deserialize_with = "serde_humantime")]
+    unregistered_block: Duration,
 }
 
 impl Default for BlacklistSettings {
@@ -22,6 +25,7 @@ impl Default for BlacklistSettings {
                 Duration::from_secs(60 * 20),
             ),
             block_extension: Duration::from_secs(600),
+            unregistered_block: Duration::ZERO,
         }
     }
 }
@@ -37,9 +41,16 @@ impl BlacklistSettings {
             invalid_token,
             push_error,
             block_extension,
+            unregistered_block: Duration::ZERO,
         }
     }
 
+    #[cfg(test)]
+    pub fn with_unregistered_block(mut self, unregistered_block: Duration) -> Self {
+        self.unregistered_block = unregistered_block;
+        self
+    }
+
     pub fn invalid_token(&self) -> &BlacklistBlockingTimes {
         &self.invalid_token
     }
@@ -51,9 +62,13 @@ impl BlacklistSettings {
     pub fn block_extension(&self) -> &Duration {
         &self.block_extension
     }
+
+    pub fn unregistered_block(&self) -> &Duration {
+        &self.unregistered_block
+    }
 }
 
-#[derive(Debug, Deserialize, Copy, Clone)]
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct BlacklistBlockingTimes {
     #[serde(deserialize_with = "serde_humantime")]
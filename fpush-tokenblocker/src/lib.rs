@@ -3,7 +3,7 @@ pub use config::BlacklistSettings;
 
 use dashmap::DashMap;
 use log::{error, info};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 pub struct FpushBlocklistValue {
     blocking_start: u64,
     blocking_end: u64,
@@ -45,6 +45,8 @@ impl FpushBlocklistValue {
 
 pub struct FpushBlocklist {
     token_blocklist: DashMap<String, FpushBlocklistValue>,
+    /// end of the block of recently unregistered tokens
+    unregistered_tokens: DashMap<String, Instant>,
     blacklist_config: BlacklistSettings,
 }
 
@@ -52,6 +54,7 @@ impl FpushBlocklist {
     pub fn new(blacklist_config: &BlacklistSettings) -> Self {
         Self {
             token_blocklist: DashMap::new(),
+            unregistered_tokens: DashMap::new(),
             blacklist_config: *blacklist_config,
         }
     }
@@ -80,6 +83,50 @@ impl FpushBlocklist {
         self.is_blocked_token(token)
     }
 
+    /// Unix timestamp until which the token is blocked, without extending the block
+    pub fn blocked_until(&self, token: &str) -> Option<u64> {
+        let blocklist_entry = self.token_blocklist.get(token)?;
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(timestamp) if blocklist_entry.is_blocked(&timestamp) => {
+                Some(blocklist_entry.blocking_end)
+            }
+            Ok(_) => None,
+            Err(_) => {
+                error!("Could not get current SystemTime");
+                None
+            }
+        }
+    }
+
+    /// Remove the token from the blocklist. Returns true if the token was blocked
+    pub fn unblock(&self, token: &str) -> bool {
+        let unregistered = self.unregistered_tokens.remove(token).is_some();
+        match self.token_blocklist.remove(token) {
+            Some(_) => {
+                info!("Unblocking token {}", token);
+                true
+            }
+            None => unregistered,
+        }
+    }
+
+    /// Drop pushes for a token for the configured time after it was unregistered
+    pub fn block_unregistered(&self, token: &str) {
+        let block_time = *self.blacklist_config.unregistered_block();
+        if block_time.is_zero() {
+            return;
+        }
+        info!("Blocking unregistered token {}", token);
+        self.unregistered_tokens
+            .insert(token.to_string(), Instant::now() + block_time);
+    }
+
+    pub fn is_unregistered(&self, token: &str) -> bool {
+        self.unregistered_tokens
+            .get(token)
+            .is_some_and(|blocked_until| *blocked_until > Instant::now())
+    }
+
     pub fn block_invalid_token(&self, token: String) {
         self.block_internal(
             token,
@@ -117,6 +164,9 @@ impl FpushBlocklist {
     }
 
     pub fn cleanup(&self) {
+        let now = Instant::now();
+        self.unregistered_tokens
+            .retain(|_, blocked_until| *blocked_until > now);
         if let Ok(timestamp) = SystemTime::now().duration_since(UNIX_EPOCH) {
             self.token_blocklist
                 .retain(|_, v| !v.is_blocked(&timestamp));
@@ -176,4 +226,45 @@ mod tests {
         assert!(!blocklist.is_blocked("some-token"));
         assert!(!blocklist.is_blocked("some-token"));
     }
+
+    #[test]
+    fn unblock() {
+        let settings = BlacklistSettings::new_debug_config(
+            BlacklistBlockingTimes::new(Duration::from_secs(10), Duration::from_secs(20)),
+            BlacklistBlockingTimes::default(),
+            Duration::from_secs(10),
+        );
+        let blocklist = FpushBlocklist::new(&settings);
+
+        assert!(!blocklist.unblock("some-token"));
+        assert!(blocklist.blocked_until("some-token").is_none());
+
+        blocklist.block_invalid_token("some-token".to_string());
+        assert!(blocklist.blocked_until("some-token").is_some());
+        assert!(blocklist.unblock("some-token"));
+        assert!(blocklist.blocked_until("some-token").is_none());
+        assert!(!blocklist.is_blocked("some-token"));
+    }
+
+    #[test]
+    fn unregistered() {
+        let settings = BlacklistSettings::default();
+        let blocklist = FpushBlocklist::new(&settings);
+
+        // disabled by default
+        blocklist.block_unregistered("some-token");
+        assert!(!blocklist.is_unregistered("some-token"));
+
+        let settings = settings.with_unregistered_block(Duration::from_secs(1));
+        let blocklist = FpushBlocklist::new(&settings);
+        blocklist.block_unregistered("some-token");
+        assert!(blocklist.is_unregistered("some-token"));
+        assert!(!blocklist.is_blocked("some-token"));
+        assert!(!blocklist.is_unregistered("other-token"));
+
+        sleep(Duration::from_millis(1100));
+        assert!(!blocklist.is_unregistered("some-token"));
+        blocklist.cleanup();
+        assert!(blocklist.unregistered_tokens.is_empty());
+    }
 }
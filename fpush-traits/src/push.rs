@@ -5,7 +5,12 @@ pub type PushResult<T> = std::result::Result<T, PushError>;
 
 #[derive(Debug, From, Display)]
 pub enum PushError {
-    CertLoading,
+    #[from(skip)]
+    #[display("could not load credentials from {path}: {reason}")]
+    CertLoading {
+        path: String,
+        reason: String,
+    },
     PushEndpointTmp,
     PushEndpointPersistent,
     TokenRateLimited,
@@ -13,8 +18,18 @@ pub enum PushError {
     Unknown(u16),
 }
 
+/// Content of the XEP-0357 `urn:xmpp:push:summary` form published by the XMPP server.
+/// All fields are optional as servers may omit any of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushSummary {
+    pub message_count: Option<u32>,
+    pub last_message_sender: Option<String>,
+    pub last_message_body: Option<String>,
+    pub pending_subscription_count: Option<u32>,
+}
+
 #[async_trait]
 pub trait PushTrait {
     /// returns false if the token should be blocked
-    async fn send(&self, token: String) -> PushResult<()>;
+    async fn send(&self, token: String, summary: &PushSummary) -> PushResult<()>;
 }
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use crate::config::fpush_config::{ReconnectBackoffConfig, TimeoutConfig};
+
+use rand::Rng;
+
+/// Delay between reconnection attempts.
+/// Without a configured backoff policy the fixed `xmppconnection_error` delay is used.
+pub(crate) struct ReconnectBackoff {
+    policy: Option<ReconnectBackoffConfig>,
+    fixed_delay: Duration,
+    current_delay: Duration,
+    failures: u32,
+}
+
+impl ReconnectBackoff {
+    pub(crate) fn new(timeout: &TimeoutConfig) -> Self {
+        let policy = timeout.reconnect_backoff().clone();
+        let current_delay = match &policy {
+            Some(policy) => *policy.initial_delay(),
+            None => *timeout.xmppconnection_error(),
+        };
+        Self {
+            policy,
+            fixed_delay: *timeout.xmppconnection_error(),
+            current_delay,
+            failures: 0,
+        }
+    }
+
+    /// Delay to wait before the next reconnection attempt
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        let policy = match &self.policy {
+            Some(policy) => policy,
+            None => return self.fixed_delay,
+        };
+        let delay = self.current_delay;
+        self.current_delay =
+            Duration::try_from_secs_f64(delay.as_secs_f64() * policy.multiplier().max(1.0))
+                .unwrap_or(*policy.max_delay())
+                .min(*policy.max_delay());
+        apply_jitter(delay, *policy.jitter())
+    }
+
+    /// Reset the backoff if the connection was stable long enough.
+    /// A reset backoff still waits the initial delay before the next reconnection attempt.
+    pub(crate) fn connection_closed(&mut self, uptime: Duration) {
+        if let Some(policy) = &self.policy {
+            if uptime >= *policy.reset_after() {
+                self.failures = 0;
+                self.current_delay = *policy.initial_delay();
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    #[inline(always)]
+    pub(crate) fn current_delay(&self) -> Duration {
+        self.current_delay
+    }
+}
+
+fn apply_jitter(delay: Duration, jitter: f64) -> Duration {
+    let jitter = jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+        return delay;
+    }
+    let factor = 1.0 + rand::thread_rng().gen_range(-jitter..=jitter);
+    delay.mul_f64(factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ReconnectBackoff;
+    use crate::config::fpush_config::TimeoutConfig;
+
+    fn backoff_config(jitter: f64) -> TimeoutConfig {
+        serde_json::from_value(serde_json::json!({
+            "xmppconnectionError": "10s",
+            "reconnectBackoff": {
+                "initialDelay": "1s",
+                "maxDelay": "5s",
+                "multiplier": 2.0,
+                "jitter": jitter,
+                "resetAfter": "60s"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn fixed_delay_without_policy() {
+        let mut backoff = ReconnectBackoff::new(&TimeoutConfig::default());
+        assert_eq!(backoff.next_delay(), Duration::from_secs(10));
+        backoff.connection_closed(Duration::from_secs(3600));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn exponential_growth_is_capped() {
+        let mut backoff = ReconnectBackoff::new(&backoff_config(0.0));
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+        assert_eq!(backoff.failures(), 5);
+    }
+
+    #[test]
+    fn reset_after_stable_connection() {
+        let mut backoff = ReconnectBackoff::new(&backoff_config(0.0));
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.connection_closed(Duration::from_secs(10));
+        assert_eq!(backoff.current_delay(), Duration::from_secs(4));
+        backoff.connection_closed(Duration::from_secs(60));
+        assert_eq!(backoff.current_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.failures(), 0);
+        // the initial delay is still awaited after a stable connection was lost
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_in_range() {
+        let mut backoff = ReconnectBackoff::new(&backoff_config(0.5));
+        for _ in 0..100 {
+            backoff.connection_closed(Duration::from_secs(60));
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1500));
+        }
+    }
+}
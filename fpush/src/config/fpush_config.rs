@@ -1,40 +1,305 @@
+use std::marker::PhantomData;
 use std::time::Duration;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use fpush_push::FpushPushConfig;
 
 use derive_getters::Getters;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
 use serde::Deserialize;
+use xmpp_parsers::jid::Jid;
 
 #[derive(Debug, Deserialize, Getters)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FpushConfig {
-    component: FpushComponentSettings,
+    /// a single component connection or a list of them
+    #[serde(rename = "component", deserialize_with = "one_or_many")]
+    components: Vec<FpushComponentSettings>,
     push_modules: FpushPushConfig,
+    /// start with the push modules that could be loaded instead of failing
+    #[serde(default)]
+    allow_degraded_start: bool,
     #[serde(default)]
     timeout: TimeoutConfig,
+    /// bare JIDs allowed to execute ad-hoc commands
+    #[serde(default)]
+    admin_jids: Vec<String>,
+    #[serde(default)]
+    origin_domains: OriginDomainSettings,
 }
 
-#[derive(Debug, Deserialize, Getters)]
+/// Domains of XMPP servers allowed or denied to request pushes.
+/// Entries are either exact domains or wildcards like `*.example.org`
+#[derive(Debug, Clone, Default, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct OriginDomainSettings {
+    /// if not empty, only matching domains may request pushes
+    allow: Vec<String>,
+    /// matching domains are rejected, even if they are allowed
+    deny: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FpushComponentSettings {
+    #[serde(default)]
+    mode: ConnectionMode,
+    /// component JID, required in component mode
+    #[serde(default)]
     component_hostname: String,
+    /// component secret, required in component mode
+    #[serde(default)]
     component_key: String,
+    /// full JID of the account, required in client mode
+    #[serde(default)]
+    jid: Option<String>,
+    /// password of the account, required in client mode
+    #[serde(default)]
+    password: Option<String>,
     server_hostname: String,
     server_port: u16,
+    /// SRV service (e.g. `_xmpp-component._tcp`) resolved for `serverHostname`,
+    /// `serverPort` is used as fallback port
+    #[serde(default)]
+    srv: Option<String>,
+    /// Endpoints tried in order after `serverHostname` failed
+    #[serde(default)]
+    fallback_endpoints: Vec<ComponentEndpointSettings>,
+    #[serde(default)]
+    tls: Option<ComponentTlsSettings>,
+    #[serde(default)]
+    keepalive: Option<KeepaliveSettings>,
+    #[serde(default)]
+    reply_batch: ReplyBatchSettings,
+    /// maximal number of stanzas handled concurrently, further requests are answered with `resource-constraint`
+    #[serde(default = "FpushComponentSettings::default_max_in_flight_requests")]
+    max_in_flight_requests: usize,
 }
 
-#[derive(Debug, Deserialize, Getters)]
+impl FpushComponentSettings {
+    fn default_max_in_flight_requests() -> usize {
+        5000
+    }
+
+    /// JID fpush uses on this connection, the component JID or the account JID in client mode
+    pub(crate) fn local_jid(&self) -> &str {
+        match self.mode {
+            ConnectionMode::Component => &self.component_hostname,
+            ConnectionMode::Client => self.jid.as_deref().unwrap_or_default(),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self.mode {
+            ConnectionMode::Component => {
+                if self.component_hostname.is_empty() || self.component_key.is_empty() {
+                    return Err(Error::Config(format!(
+                        "Component connection to {} requires componentHostname and componentKey",
+                        self.server_hostname
+                    )));
+                }
+                Jid::new(&self.component_hostname).map_err(|e| {
+                    Error::Config(format!(
+                        "Invalid componentHostname {}: {}",
+                        self.component_hostname, e
+                    ))
+                })?;
+            }
+            ConnectionMode::Client => {
+                let (jid, password) = match (&self.jid, &self.password) {
+                    (Some(jid), Some(password)) => (jid, password),
+                    _ => {
+                        return Err(Error::Config(format!(
+                            "Client connection to {} requires jid and password",
+                            self.server_hostname
+                        )))
+                    }
+                };
+                let parsed_jid = Jid::new(jid)
+                    .map_err(|e| Error::Config(format!("Invalid client jid {}: {}", jid, e)))?;
+                if parsed_jid.node().is_none() || password.is_empty() {
+                    return Err(Error::Config(format!(
+                        "Client connection {} requires the JID of an account and its password",
+                        jid
+                    )));
+                }
+            }
+        }
+        if let Some(keepalive) = &self.keepalive {
+            keepalive.validate(&self.server_hostname)?;
+        }
+        Ok(())
+    }
+}
+
+/// How fpush connects to the XMPP server
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ConnectionMode {
+    /// XEP-0114 component connection
+    #[default]
+    Component,
+    /// login as a normal XMPP account
+    Client,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct ReplyBatchSettings {
+    /// maximal number of replies written with a single flush
+    max_size: usize,
+    /// time to wait for further replies before flushing. Zero only batches already queued replies
+    #[serde(deserialize_with = "serde_humantime")]
+    window: Duration,
+}
+
+impl Default for ReplyBatchSettings {
+    fn default() -> Self {
+        Self {
+            max_size: 64,
+            window: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct KeepaliveSettings {
+    #[serde(deserialize_with = "serde_humantime")]
+    interval: Duration,
+    /// number of unanswered pings before the connection is considered dead
+    max_missed_pongs: u32,
+    /// JID the pings are sent to. Defaults to `serverHostname`
+    target: Option<String>,
+}
+
+impl KeepaliveSettings {
+    fn validate(&self, server_hostname: &str) -> Result<()> {
+        if self.interval.is_zero() {
+            return Err(Error::Config(
+                "Keepalive interval has to be greater than zero".to_string(),
+            ));
+        }
+        if self.max_missed_pongs == 0 {
+            return Err(Error::Config(
+                "Keepalive maxMissedPongs has to be at least 1".to_string(),
+            ));
+        }
+        let target = self.target.as_deref().unwrap_or(server_hostname);
+        Jid::new(target)
+            .map_err(|e| Error::Config(format!("Invalid keepalive target {}: {}", target, e)))?;
+        Ok(())
+    }
+}
+
+impl Default for KeepaliveSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            max_missed_pongs: 3,
+            target: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ComponentEndpointSettings {
+    server_hostname: String,
+    server_port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ComponentTlsSettings {
+    mode: ComponentTlsMode,
+    /// PEM file with the CA certificates used to verify the XMPP server
+    ca_bundle_path: Option<String>,
+    /// PEM encoded client certificate chain presented to the XMPP server
+    client_cert_path: Option<String>,
+    /// PEM encoded private key of the client certificate
+    client_key_path: Option<String>,
+    /// SHA-256 fingerprints of accepted server certificates
+    #[serde(default)]
+    pinned_certificates: Vec<String>,
+    /// Name used for SNI and certificate validation. Defaults to `serverHostname`
+    server_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ComponentTlsMode {
+    DirectTls,
+    StartTls,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct TimeoutConfig {
     #[serde(deserialize_with = "serde_humantime")]
     xmppconnection_error: std::time::Duration,
+    /// exponential backoff replacing the fixed `xmppconnection_error` delay
+    #[serde(default)]
+    reconnect_backoff: Option<ReconnectBackoffConfig>,
+    /// time to wait for requests in flight on shutdown
+    #[serde(
+        default = "TimeoutConfig::default_shutdown_deadline",
+        deserialize_with = "serde_humantime"
+    )]
+    shutdown_deadline: Duration,
+    /// replies queued longer, e.g. during a reconnect, are dropped
+    #[serde(
+        default = "TimeoutConfig::default_max_reply_age",
+        deserialize_with = "serde_humantime"
+    )]
+    max_reply_age: Duration,
+}
+
+impl TimeoutConfig {
+    fn default_shutdown_deadline() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn default_max_reply_age() -> Duration {
+        Duration::from_secs(60)
+    }
 }
 
 impl Default for TimeoutConfig {
     fn default() -> Self {
         Self {
             xmppconnection_error: Duration::from_secs(10),
+            reconnect_backoff: None,
+            shutdown_deadline: Self::default_shutdown_deadline(),
+            max_reply_age: Self::default_max_reply_age(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct ReconnectBackoffConfig {
+    #[serde(deserialize_with = "serde_humantime")]
+    initial_delay: Duration,
+    #[serde(deserialize_with = "serde_humantime")]
+    max_delay: Duration,
+    multiplier: f64,
+    /// random deviation of each delay as fraction of the delay (0.0 - 1.0)
+    jitter: f64,
+    /// time a connection has to stay up to reset the backoff
+    #[serde(deserialize_with = "serde_humantime")]
+    reset_after: Duration,
+}
+
+impl Default for ReconnectBackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(300),
+            multiplier: 2.0,
+            jitter: 0.2,
+            reset_after: Duration::from_secs(60),
         }
     }
 }
@@ -47,12 +312,104 @@ where
         .map(|wrapped_de: serde_humantime::De<Duration>| wrapped_de.into_inner())
 }
 
+/// Accepts a single object or a list of objects.
+/// Decided by the JSON type, hence errors of the inner objects are reported as they are
+pub fn one_or_many<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OneOrMany<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("an object or a list of objects")
+        }
+
+        fn visit_seq<A>(self, seq: A) -> std::result::Result<Vec<T>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+
+        fn visit_map<A>(self, map: A) -> std::result::Result<Vec<T>, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            T::deserialize(MapAccessDeserializer::new(map)).map(|value| vec![value])
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(PhantomData))
+}
+
 // use serde to parse from file to struct
 pub(crate) fn load_config(config_path: &str) -> Result<FpushConfig> {
     let settings_file = std::fs::File::open(config_path)?;
     let settings_reader = std::io::BufReader::new(settings_file);
 
     let config: FpushConfig = serde_json::from_reader(settings_reader)?;
+    if config.components().is_empty() {
+        return Err(Error::Config(
+            "At least one component connection has to be configured".to_string(),
+        ));
+    }
+    for component in config.components() {
+        component.validate()?;
+    }
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Components {
+        #[serde(rename = "component", deserialize_with = "one_or_many")]
+        components: Vec<FpushComponentSettings>,
+    }
+
+    fn component(server_hostname: &str) -> serde_json::Value {
+        serde_json::json!({
+            "componentHostname": "push.example.org",
+            "componentKey": "key",
+            "serverHostname": server_hostname,
+            "serverPort": 5347
+        })
+    }
+
+    #[test]
+    fn one_or_many_components() {
+        let single: Components =
+            serde_json::from_value(serde_json::json!({ "component": component("a.example.org") }))
+                .unwrap();
+        assert_eq!(single.components.len(), 1);
+        let list: Components = serde_json::from_value(serde_json::json!({
+            "component": [component("a.example.org"), component("b.example.org")]
+        }))
+        .unwrap();
+        assert_eq!(list.components.len(), 2);
+        assert_eq!(list.components[1].server_hostname(), "b.example.org");
+    }
+
+    #[test]
+    fn one_or_many_keeps_inner_error() {
+        let mut invalid = component("b.example.org");
+        invalid.as_object_mut().unwrap().remove("serverPort");
+        for components in [
+            invalid.clone(),
+            serde_json::json!([component("a.example.org"), invalid]),
+        ] {
+            let e = serde_json::from_value::<Components>(
+                serde_json::json!({ "component": components }),
+            )
+            .unwrap_err();
+            assert!(e.to_string().contains("serverPort"), "{}", e);
+        }
+    }
+}
@@ -8,10 +8,40 @@ pub enum Error {
     ConfigError(serde_json::Error),
     Config(String),
     Xmpp(Box<tokio_xmpp::Error>),
+    TlsHandshake(TlsHandshakeError),
+    ClientLogin(ClientLoginError),
+    #[display("PubSubNonPublish: pubsub request is not a publish")]
     PubSubNonPublish,
+    #[display("PubSubInvalidFormat: pubsub payload could not be parsed")]
     PubSubInvalidFormat,
-    PubSubToManyPublishOptions,
-    PubSubInvalidPushModuleConfiguration,
+    PublishOptions(PublishOptionsError),
+}
+
+/// Reasons to reject the publish-options of a push request
+#[derive(Debug, Display)]
+pub enum PublishOptionsError {
+    #[display("publish-options field without var")]
+    MissingVar,
+    #[display("publish-options field {_0} is set more than once")]
+    DuplicateField(String),
+    #[display("publish-options contain more than {_0} fields")]
+    TooManyFields(usize),
+    #[display("publish-options have unexpected FORM_TYPE {_0}")]
+    WrongFormType(String),
+}
+
+#[derive(Debug, Display)]
+pub enum TlsHandshakeError {
+    StartTlsNotOffered,
+    StartTlsRefused,
+    Handshake(std::io::Error),
+}
+
+/// Reasons a login in client mode failed
+#[derive(Debug, Display)]
+pub enum ClientLoginError {
+    #[display("connection closed during login")]
+    Disconnected,
 }
 
 impl std::convert::From<tokio_xmpp::Error> for Error {
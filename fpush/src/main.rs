@@ -1,10 +1,21 @@
+mod backoff;
 mod config;
 mod error;
 mod xmpp;
+use backoff::ReconnectBackoff;
+use config::fpush_config::{FpushComponentSettings, TimeoutConfig};
+use error::Error;
 use fpush_push::FpushPush;
+use xmpp::{
+    AdminCommands, ComponentEndpoint, ComponentTlsConnector, OriginFilter, ReplyQueue,
+    RequestContext, ServerStatus,
+};
 
 use log::{debug, error, info};
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
 
 /// init env_logger
 fn setup_logging() {
@@ -36,27 +47,236 @@ async fn main() {
         }
     };
 
-    let push_impl: Arc<FpushPush> = Arc::new(FpushPush::new(settings.push_modules()).await);
+    let push_impl: Arc<FpushPush> =
+        match FpushPush::new(settings.push_modules(), *settings.allow_degraded_start()).await {
+            Ok(push_impl) => Arc::new(push_impl),
+            Err(e) => {
+                panic!("Error loading push modules: {}", e);
+            }
+        };
+    let admin_commands = match AdminCommands::new(settings.admin_jids()) {
+        Ok(admin_commands) => admin_commands,
+        Err(e) => {
+            panic!("Error loading admin JIDs: {}", e);
+        }
+    };
+    let origin_filter = match OriginFilter::new(settings.origin_domains()) {
+        Ok(origin_filter) => origin_filter,
+        Err(e) => {
+            panic!("Error loading origin domains: {}", e);
+        }
+    };
+    let connections = settings
+        .components()
+        .iter()
+        .map(|component_settings| Arc::new(ServerStatus::new(component_settings)))
+        .collect();
+    let context = Arc::new(RequestContext::new(
+        push_impl,
+        admin_commands,
+        origin_filter,
+        connections,
+    ));
+
+    let (shutdown_sender, shutdown) = watch::channel(false);
+
+    // one reconnect loop per configured XMPP server, all sharing the same push modules
+    let mut component_loops = Vec::with_capacity(settings.components().len());
+    for (component_settings, status) in settings.components().iter().zip(context.connections()) {
+        let tls_connector = match component_settings.tls() {
+            Some(tls_settings) => {
+                match ComponentTlsConnector::new(component_settings, tls_settings) {
+                    Ok(tls_connector) => Some(tls_connector),
+                    Err(e) => {
+                        panic!("Error loading component TLS settings: {}", e);
+                    }
+                }
+            }
+            None => None,
+        };
+        component_loops.push(tokio::spawn(component_reconnect_loop(
+            component_settings.clone(),
+            status.clone(),
+            tls_connector,
+            settings.timeout().clone(),
+            context.clone(),
+            shutdown.clone(),
+        )));
+    }
 
+    wait_for_shutdown_signal(settings_filename, &context).await;
+    if shutdown_sender.send(true).is_err() {
+        error!("All component connection loops already terminated");
+    }
+
+    for component_loop in component_loops {
+        if let Err(e) = component_loop.await {
+            error!("Component connection loop terminated: {}", e);
+        }
+    }
+    info!("Shutdown complete");
+}
+
+/// Wait for SIGTERM or SIGINT, reload origin domains and push modules on SIGHUP meanwhile
+async fn wait_for_shutdown_signal(settings_filename: &str, context: &RequestContext) {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            panic!("Could not register SIGTERM handler: {}", e);
+        }
+    };
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            panic!("Could not register SIGHUP handler: {}", e);
+        }
+    };
     loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received SIGINT, shutting down");
+                return;
+            }
+            _ = sigterm.recv() => {
+                info!("Received SIGTERM, shutting down");
+                return;
+            }
+            _ = sighup.recv() => {
+                info!("Received SIGHUP, reloading config file {}", settings_filename);
+                reload_config(settings_filename, context).await;
+            }
+        }
+    }
+}
+
+/// Replace the origin domain lists and push modules.
+/// Each part keeps its current state if its new settings are invalid
+async fn reload_config(settings_filename: &str, context: &RequestContext) {
+    let settings = match crate::config::load_config(settings_filename) {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!("Could not reload config file: {}", e);
+            return;
+        }
+    };
+    match context.origin_filter().reload(settings.origin_domains()) {
+        Ok(()) => info!("Reloaded origin domains"),
+        Err(e) => error!("Could not reload origin domains: {}", e),
+    }
+    match context
+        .push_modules()
+        .reload(settings.push_modules(), *settings.allow_degraded_start())
+        .await
+    {
+        Ok(()) => info!("Reloaded push modules"),
+        Err(e) => error!("Could not reload push modules, keeping current ones: {}", e),
+    }
+}
+
+async fn component_reconnect_loop(
+    component_settings: FpushComponentSettings,
+    status: Arc<ServerStatus>,
+    tls_connector: Option<ComponentTlsConnector>,
+    timeout: TimeoutConfig,
+    context: Arc<RequestContext>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let endpoints = ComponentEndpoint::from_settings(&component_settings);
+    let mut endpoint_index = 0;
+    let mut backoff = ReconnectBackoff::new(&timeout);
+    // replies of requests finishing during a reconnect are sent on the next connection
+    let mut replies = ReplyQueue::new(&component_settings, &timeout);
+    while !*shutdown.borrow() {
+        let endpoint = &endpoints[endpoint_index];
         info!(
-            "Opening connection to {}",
-            settings.component().server_hostname()
+            "Opening connection to {} via {}",
+            status.name(),
+            endpoint.name()
         );
-        // open component connection
-        match crate::xmpp::init_component_connection(&settings).await {
-            Err(e) => {
-                error!("Could not connect to XMPP Server {}", e);
-                info!(
-                    "Waiting {} seconds before reconnecting",
-                    settings.timeout().xmppconnection_error().as_secs()
-                );
-                tokio::time::sleep(*settings.timeout().xmppconnection_error()).await;
+        status.connection_attempt();
+        // open component connection, a hanging connection attempt must not delay the shutdown
+        let connection = tokio::select! {
+            connection = crate::xmpp::init_component_connection(
+                &component_settings,
+                endpoint,
+                tls_connector.as_ref(),
+            ) => connection,
+            _ = shutdown.changed() => {
+                info!("Aborting connection attempt to {} on shutdown", status.name());
+                break;
             }
+        };
+        match connection {
             Ok(component) => {
+                status.set_connected(true);
+                info!("Connected to {} via {}", status.name(), endpoint.name());
+                let connected_since = Instant::now();
                 // open new messageLoop
-                crate::xmpp::message_loop_main_thread(component, push_impl.clone()).await;
+                crate::xmpp::message_loop_main_thread(
+                    component,
+                    &component_settings,
+                    context.clone(),
+                    status.clone(),
+                    &mut replies,
+                    &mut shutdown,
+                )
+                .await;
+                status.set_connected(false);
+                info!("{}", status);
+                if *shutdown.borrow() {
+                    break;
+                }
+                // always prefer the first endpoint after a connection loss
+                endpoint_index = 0;
+                backoff.connection_closed(connected_since.elapsed());
+                // a server dropping connections right away must not cause a reconnect loop
+                wait_before_reconnect(&mut backoff, &status, &mut shutdown).await;
+            }
+            Err(e) => {
+                status.connection_failed();
+                if let Error::TlsHandshake(e) = e {
+                    error!(
+                        "TLS handshake with XMPP Server {} via {} failed: {}",
+                        status.name(),
+                        endpoint.name(),
+                        e
+                    );
+                } else {
+                    error!(
+                        "Could not connect to XMPP Server {} via {}: {}",
+                        status.name(),
+                        endpoint.name(),
+                        e
+                    );
+                }
+                endpoint_index += 1;
+                if endpoint_index < endpoints.len() {
+                    info!("Trying next endpoint {}", endpoints[endpoint_index].name());
+                } else {
+                    endpoint_index = 0;
+                    info!("All endpoints of {} failed", status.name());
+                    wait_before_reconnect(&mut backoff, &status, &mut shutdown).await;
+                }
             }
         }
     }
 }
+
+async fn wait_before_reconnect(
+    backoff: &mut ReconnectBackoff,
+    status: &ServerStatus,
+    shutdown: &mut watch::Receiver<bool>,
+) {
+    let delay = backoff.next_delay();
+    info!(
+        "{}: Waiting {}ms before reconnecting (failed attempts: {}, next backoff: {}ms)",
+        status.name(),
+        delay.as_millis(),
+        backoff.failures(),
+        backoff.current_delay().as_millis()
+    );
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => {}
+        _ = shutdown.changed() => {}
+    }
+}
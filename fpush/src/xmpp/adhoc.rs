@@ -0,0 +1,373 @@
+use crate::error::{Error, Result};
+use crate::xmpp::error_messages::{send_error_iq, send_forbidden_iq, send_item_not_found_iq};
+use crate::xmpp::reply_queue::ReplySender;
+use crate::xmpp::request_context::RequestContext;
+use crate::xmpp::server_status::ServerStatus;
+use fpush_push::{FpushPushArc, PushRequestError};
+
+use log::{error, info, warn};
+use rand::Rng;
+use std::sync::Arc;
+use xmpp::agent::Element;
+use xmpp_parsers::data_forms::DataForm;
+use xmpp_parsers::iq::{Iq, IqType};
+use xmpp_parsers::jid::{BareJid, Jid};
+use xmpp_parsers::ns;
+
+pub(crate) const COMMANDS_NS: &str = "http://jabber.org/protocol/commands";
+
+const LIST_MODULES: &str = "list-modules";
+const MODULE_STATS: &str = "module-stats";
+const CONNECTION_STATS: &str = "connection-stats";
+const TOKEN_STATUS: &str = "token-status";
+const UNBLOCK_TOKEN: &str = "unblock-token";
+const GENERATE_SECRET: &str = "generate-secret";
+
+/// node and name of all available commands
+const COMMANDS: [(&str, &str); 6] = [
+    (LIST_MODULES, "List push modules and their health"),
+    (MODULE_STATS, "Show push module counters"),
+    (CONNECTION_STATS, "Show XMPP connection counters"),
+    (
+        TOKEN_STATUS,
+        "Look up ratelimit and blocklist state of a token",
+    ),
+    (UNBLOCK_TOKEN, "Remove a token from the blocklist"),
+    (GENERATE_SECRET, "Generate the push secret of a token"),
+];
+
+/// XEP-0050 ad-hoc commands restricted to the configured admin JIDs
+pub(crate) struct AdminCommands {
+    admin_jids: Vec<BareJid>,
+}
+
+impl AdminCommands {
+    pub(crate) fn new(admin_jids: &[String]) -> Result<Self> {
+        let admin_jids = admin_jids
+            .iter()
+            .map(|jid| {
+                BareJid::new(jid)
+                    .map_err(|e| Error::Config(format!("Invalid admin JID {}: {}", jid, e)))
+            })
+            .collect::<Result<Vec<BareJid>>>()?;
+        Ok(Self { admin_jids })
+    }
+
+    #[inline(always)]
+    fn is_admin(&self, jid: &Jid) -> bool {
+        self.admin_jids.contains(&jid.to_bare())
+    }
+
+    /// Answer disco#items requests for the commands node
+    pub(crate) async fn handle_command_list(
+        &self,
+        conn: &ReplySender,
+        iq_id: &str,
+        from: Jid,
+        to: Jid,
+    ) {
+        if !self.is_admin(&from) {
+            warn!("Denied command list to non-admin {}", from);
+            send_forbidden_iq(conn, iq_id, from, to).await;
+            return;
+        }
+        let query = Element::builder("query", ns::DISCO_ITEMS)
+            .attr("node", COMMANDS_NS)
+            .append_all(COMMANDS.iter().map(|(node, name)| {
+                Element::builder("item", ns::DISCO_ITEMS)
+                    .attr("jid", to.to_string())
+                    .attr("node", *node)
+                    .attr("name", *name)
+                    .build()
+            }))
+            .build();
+        send_result(conn, iq_id, query, from, to).await;
+    }
+
+    /// Execute a command sent by an admin
+    pub(crate) async fn handle_command(
+        &self,
+        conn: &ReplySender,
+        context: &RequestContext,
+        iq_id: &str,
+        iq_payload: Element,
+        from: Jid,
+        to: Jid,
+    ) {
+        if !self.is_admin(&from) {
+            warn!("Denied ad-hoc command from non-admin {}", from);
+            send_forbidden_iq(conn, iq_id, from, to).await;
+            return;
+        }
+        let node = match iq_payload.attr("node") {
+            Some(node) => node.to_string(),
+            None => {
+                send_error_iq(conn, iq_id, from, to).await;
+                return;
+            }
+        };
+        info!("Executing ad-hoc command {} for {}", node, from);
+        let push_modules = context.push_modules();
+        let session_id = iq_payload
+            .attr("sessionid")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("fpush-{:016x}", rand::thread_rng().gen::<u64>()));
+        if iq_payload.attr("action") == Some("cancel") {
+            let response = command_response(&node, &session_id, "canceled", None);
+            send_result(conn, iq_id, response, from, to).await;
+            return;
+        }
+        let submitted_form = iq_payload
+            .get_child("x", ns::DATA_FORMS)
+            .and_then(|form| DataForm::try_from(form.clone()).ok());
+
+        let response = match node.as_str() {
+            LIST_MODULES => list_modules(push_modules, &node, &session_id),
+            MODULE_STATS => module_stats(push_modules, &node, &session_id),
+            CONNECTION_STATS => connection_stats(context.connections(), &node, &session_id),
+            TOKEN_STATUS | UNBLOCK_TOKEN | GENERATE_SECRET => match submitted_form {
+                None => token_form(push_modules, &node, &session_id),
+                Some(form) => match token_command(push_modules, &node, &form) {
+                    Some(note) => command_response(&node, &session_id, "completed", Some(note)),
+                    None => {
+                        info!("Incomplete ad-hoc command {} from {}", node, from);
+                        send_error_iq(conn, iq_id, from, to).await;
+                        return;
+                    }
+                },
+            },
+            _ => {
+                send_item_not_found_iq(conn, iq_id, from, to).await;
+                return;
+            }
+        };
+        send_result(conn, iq_id, response, from, to).await;
+    }
+}
+
+fn list_modules(push_modules: &FpushPushArc, node: &str, session_id: &str) -> Element {
+    let fields = push_modules.module_ids().into_iter().map(|module_id| {
+        let health = match push_modules.module_stats(&module_id) {
+            Some(stats) if stats.is_healthy() => "healthy".to_string(),
+            Some(stats) => format!(
+                "failing ({} consecutive errors)",
+                stats.consecutive_errors()
+            ),
+            None => "unknown".to_string(),
+        };
+        let label = format!(
+            "{} ({})",
+            module_id,
+            push_modules.module_type(&module_id).unwrap_or("unknown")
+        );
+        form_field(&module_id, "text-single", &label, &[health])
+    });
+    let failed_fields = push_modules
+        .failed_modules()
+        .into_iter()
+        .map(|(module_id, reason)| {
+            form_field(
+                &module_id,
+                "text-single",
+                &format!("{} (not loaded)", module_id),
+                &[format!("failed: {}", reason)],
+            )
+        });
+    let form = data_form("result", "Push modules", fields.chain(failed_fields));
+    command_response(node, session_id, "completed", Some(form))
+}
+
+fn module_stats(push_modules: &FpushPushArc, node: &str, session_id: &str) -> Element {
+    let fields = push_modules
+        .module_ids()
+        .into_iter()
+        .filter_map(|module_id| {
+            let stats = push_modules.module_stats(&module_id)?;
+            Some(form_field(
+                &module_id,
+                "text-single",
+                &module_id,
+                &[stats.to_string()],
+            ))
+        });
+    let form = data_form("result", "Push module counters", fields);
+    command_response(node, session_id, "completed", Some(form))
+}
+
+fn connection_stats(connections: &[Arc<ServerStatus>], node: &str, session_id: &str) -> Element {
+    let fields = connections.iter().map(|status| {
+        form_field(
+            status.name(),
+            "text-single",
+            status.name(),
+            &[status.to_string()],
+        )
+    });
+    let form = data_form("result", "XMPP connection counters", fields);
+    command_response(node, session_id, "completed", Some(form))
+}
+
+/// First step of the token commands: ask for push module and token
+fn token_form(push_modules: &FpushPushArc, node: &str, session_id: &str) -> Element {
+    let module_field = Element::builder("field", ns::DATA_FORMS)
+        .attr("var", "pushModule")
+        .attr("type", "list-single")
+        .attr("label", "Push module")
+        .append(Element::builder("required", ns::DATA_FORMS).build())
+        .append_all(push_modules.module_ids().into_iter().map(|module_id| {
+            Element::builder("option", ns::DATA_FORMS)
+                .append(
+                    Element::builder("value", ns::DATA_FORMS)
+                        .append(module_id)
+                        .build(),
+                )
+                .build()
+        }))
+        .build();
+    let token_field = Element::builder("field", ns::DATA_FORMS)
+        .attr("var", "token")
+        .attr("type", "text-single")
+        .attr("label", "Token")
+        .append(Element::builder("required", ns::DATA_FORMS).build())
+        .build();
+    let mut fields = vec![module_field, token_field];
+    if node == GENERATE_SECRET {
+        fields.push(
+            Element::builder("field", ns::DATA_FORMS)
+                .attr("var", "originDomain")
+                .attr("type", "text-single")
+                .attr("label", "XMPP server domain (if the secret includes it)")
+                .build(),
+        );
+    }
+    let form = data_form("form", "Select token", fields.into_iter());
+    Element::builder("command", COMMANDS_NS)
+        .attr("node", node)
+        .attr("sessionid", session_id)
+        .attr("status", "executing")
+        .append(
+            Element::builder("actions", COMMANDS_NS)
+                .attr("execute", "complete")
+                .append(Element::builder("complete", COMMANDS_NS).build())
+                .build(),
+        )
+        .append(form)
+        .build()
+}
+
+/// Second step of the token commands: run the lookup, unblock or secret generation on the submitted form.
+/// Returns None if the form is incomplete
+fn token_command(push_modules: &FpushPushArc, node: &str, form: &DataForm) -> Option<Element> {
+    let mut module_id = None;
+    let mut token = None;
+    let mut origin_domain = None;
+    for field in &form.fields {
+        match field.var.as_deref() {
+            Some("pushModule") => module_id = field.values.first(),
+            Some("token") => token = field.values.first(),
+            Some("originDomain") => origin_domain = field.values.first(),
+            _ => {}
+        }
+    }
+    let (module_id, token) = match (module_id, token) {
+        (Some(module_id), Some(token)) if !token.is_empty() => (module_id, token),
+        _ => return None,
+    };
+    let text = match node {
+        UNBLOCK_TOKEN => match push_modules.unblock_token(module_id, token) {
+            Ok(true) => {
+                info!("{}: Token {} unblocked by admin", module_id, token);
+                format!("Token unblocked in {}", module_id)
+            }
+            Ok(false) => format!("Token was not blocked in {}", module_id),
+            Err(PushRequestError::UnknownPushModule) => {
+                format!("Unknown push module {}", module_id)
+            }
+            Err(e) => format!("Could not unblock token: {}", e),
+        },
+        GENERATE_SECRET => {
+            let origin_domain = origin_domain.map(String::as_str).unwrap_or_default();
+            match push_modules.generate_secret(module_id, token, origin_domain) {
+                Ok(Some(secret)) => secret,
+                Ok(None) => format!("{} does not require a secret", module_id),
+                Err(PushRequestError::UnknownPushModule) => {
+                    format!("Unknown push module {}", module_id)
+                }
+                Err(e) => format!("Could not generate secret: {}", e),
+            }
+        }
+        _ => match push_modules.token_status(module_id, token) {
+            Ok(token_status) => format!(
+                "{}: ratelimited={} blocked={}",
+                module_id,
+                token_status.ratelimited,
+                match token_status.blocked_until {
+                    Some(blocked_until) => format!("until {}", blocked_until),
+                    None => "no".to_string(),
+                }
+            ),
+            Err(PushRequestError::UnknownPushModule) => {
+                format!("Unknown push module {}", module_id)
+            }
+            Err(e) => format!("Could not look up token: {}", e),
+        },
+    };
+    Some(
+        Element::builder("note", COMMANDS_NS)
+            .attr("type", "info")
+            .append(text)
+            .build(),
+    )
+}
+
+fn command_response(
+    node: &str,
+    session_id: &str,
+    status: &str,
+    payload: Option<Element>,
+) -> Element {
+    let mut response = Element::builder("command", COMMANDS_NS)
+        .attr("node", node)
+        .attr("sessionid", session_id)
+        .attr("status", status);
+    if let Some(payload) = payload {
+        response = response.append(payload);
+    }
+    response.build()
+}
+
+fn data_form(type_: &str, title: &str, fields: impl Iterator<Item = Element>) -> Element {
+    Element::builder("x", ns::DATA_FORMS)
+        .attr("type", type_)
+        .append(
+            Element::builder("title", ns::DATA_FORMS)
+                .append(title)
+                .build(),
+        )
+        .append_all(fields)
+        .build()
+}
+
+fn form_field(var: &str, type_: &str, label: &str, values: &[String]) -> Element {
+    Element::builder("field", ns::DATA_FORMS)
+        .attr("var", var)
+        .attr("type", type_)
+        .attr("label", label)
+        .append_all(values.iter().map(|value| {
+            Element::builder("value", ns::DATA_FORMS)
+                .append(value.as_str())
+                .build()
+        }))
+        .build()
+}
+
+async fn send_result(conn: &ReplySender, iq_id: &str, payload: Element, from: Jid, to: Jid) {
+    let mut iq = Iq::empty_result(from, iq_id.to_string()).with_from(to);
+    iq.payload = IqType::Result(Some(payload));
+    if let Err(e) = conn.send(iq).await {
+        error!(
+            "Could not forward ad-hoc command result to main handler: {}",
+            e
+        );
+    }
+}
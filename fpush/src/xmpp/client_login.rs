@@ -0,0 +1,30 @@
+use crate::error::{ClientLoginError, Result};
+use crate::xmpp::connector::{ComponentConnector, ComponentStream};
+
+use futures::StreamExt;
+use log::info;
+use tokio_xmpp::{Client, Event};
+use xmpp_parsers::jid::Jid;
+
+pub(crate) type FpushClient = Client<ComponentConnector>;
+
+/// Log in as normal XMPP account over the already established `stream`.
+/// tokio_xmpp negotiates SASL (SCRAM or PLAIN) and binds the resource of `jid`.
+pub(crate) async fn login_client(
+    stream: ComponentStream,
+    jid: Jid,
+    password: &str,
+) -> Result<FpushClient> {
+    let mut client =
+        Client::new_with_connector(jid, password.to_owned(), ComponentConnector::new(stream));
+    // reconnects are handled by the reconnect loop for both connection modes
+    client.set_reconnect(false);
+    match client.next().await {
+        Some(Event::Online { bound_jid, .. }) => {
+            info!("Logged in as {}", bound_jid);
+            Ok(client)
+        }
+        Some(Event::Disconnected(e)) => Err(e.into()),
+        _ => Err(ClientLoginError::Disconnected.into()),
+    }
+}
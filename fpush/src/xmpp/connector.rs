@@ -0,0 +1,289 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::config::fpush_config::FpushComponentSettings;
+use crate::error::{Result, TlsHandshakeError};
+use crate::xmpp::client_login::FpushClient;
+
+use futures::{ready, Sink, SinkExt, Stream, StreamExt};
+use log::error;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_xmpp::connect::{DnsConfig, ServerConnector};
+use tokio_xmpp::xmpp_stream::XmppStream;
+use tokio_xmpp::{Component, Event, Packet};
+use xmpp::agent::Element;
+use xmpp_parsers::{jid::Jid, minidom::Node, ns};
+
+pub(crate) type FpushComponent = Component<ComponentConnector>;
+/// Established connection to a XMPP server, either as component or as client.
+/// Both are used as stream and sink of stanzas in the `jabber:client` namespace.
+pub(crate) enum XmppConnection {
+    Component(FpushComponent),
+    Client(Box<FpushClient>),
+}
+
+impl Stream for XmppConnection {
+    type Item = Element;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            XmppConnection::Component(component) => {
+                Pin::new(component).poll_next(cx).map(|stanza| {
+                    stanza.map(|stanza| rebind_ns(stanza, ns::COMPONENT_ACCEPT, ns::JABBER_CLIENT))
+                })
+            }
+            XmppConnection::Client(client) => loop {
+                match ready!(Pin::new(&mut **client).poll_next(cx)) {
+                    Some(Event::Stanza(stanza)) => return Poll::Ready(Some(stanza)),
+                    Some(Event::Online { .. }) => {}
+                    Some(Event::Disconnected(e)) => {
+                        error!("Client connection lost: {}", e);
+                        return Poll::Ready(None);
+                    }
+                    None => return Poll::Ready(None),
+                }
+            },
+        }
+    }
+}
+
+impl Sink<Element> for XmppConnection {
+    type Error = tokio_xmpp::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            XmppConnection::Component(component) => Pin::new(component).poll_ready(cx),
+            XmppConnection::Client(client) => Pin::new(&mut **client).poll_ready(cx),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, stanza: Element) -> Result<(), Self::Error> {
+        match self.get_mut() {
+            XmppConnection::Component(component) => Pin::new(component).start_send(rebind_ns(
+                stanza,
+                ns::JABBER_CLIENT,
+                ns::COMPONENT_ACCEPT,
+            )),
+            XmppConnection::Client(client) => {
+                Pin::new(&mut **client).start_send(Packet::Stanza(stanza))
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            XmppConnection::Component(component) => Pin::new(component).poll_flush(cx),
+            XmppConnection::Client(client) => Pin::new(&mut **client).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            XmppConnection::Component(component) => Pin::new(component).poll_close(cx),
+            XmppConnection::Client(client) => Pin::new(&mut **client).poll_close(cx),
+        }
+    }
+}
+
+/// Move a stanza of a component stream between `jabber:component:accept` and the
+/// `jabber:client` namespace xmpp_parsers expects. Only the stanza and its children in
+/// namespace `from` (e.g. `<error/>`) are rebuilt, payloads in other namespaces are moved over.
+fn rebind_ns(mut element: Element, from: &str, to: &str) -> Element {
+    if element.ns() != from {
+        return element;
+    }
+    let mut builder = Element::builder(element.name(), to);
+    for (name, value) in element.attrs() {
+        builder = builder.attr(name, value);
+    }
+    for node in element.nodes_mut() {
+        builder = match std::mem::replace(node, Node::Text(String::new())) {
+            Node::Element(child) => builder.append(rebind_ns(child, from, to)),
+            text => builder.append(text),
+        };
+    }
+    builder.build()
+}
+
+/// A single address of the component endpoint of a XMPP server
+pub(crate) struct ComponentEndpoint {
+    name: String,
+    dns_config: DnsConfig,
+}
+
+impl ComponentEndpoint {
+    /// All endpoints of a component connection in the order they should be tried
+    pub(crate) fn from_settings(component: &FpushComponentSettings) -> Vec<Self> {
+        let mut endpoints = Vec::with_capacity(1 + component.fallback_endpoints().len());
+        endpoints.push(match component.srv() {
+            Some(srv) => Self {
+                name: format!("{}.{}", srv, component.server_hostname()),
+                dns_config: DnsConfig::srv(
+                    component.server_hostname(),
+                    srv,
+                    *component.server_port(),
+                ),
+            },
+            None => Self::no_srv(component.server_hostname(), *component.server_port()),
+        });
+        for fallback in component.fallback_endpoints() {
+            endpoints.push(Self::no_srv(
+                fallback.server_hostname(),
+                *fallback.server_port(),
+            ));
+        }
+        endpoints
+    }
+
+    fn no_srv(host: &str, port: u16) -> Self {
+        Self {
+            name: format!("{}:{}", host, port),
+            dns_config: DnsConfig::no_srv(host, port),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) async fn connect(&self) -> Result<TcpStream> {
+        Ok(self.dns_config.resolve().await?)
+    }
+}
+
+/// Transport of a component connection, either plain tcp or TLS protected
+pub(crate) enum ComponentStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for ComponentStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ComponentStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            ComponentStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ComponentStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            ComponentStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            ComponentStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ComponentStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            ComponentStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ComponentStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            ComponentStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Hands an already established (and possibly TLS protected) stream to tokio_xmpp.
+/// This keeps connection and handshake errors on our side instead of tokio_xmpp
+/// wrapping them into generic connection errors.
+#[derive(Clone)]
+pub(crate) struct ComponentConnector {
+    stream: Arc<Mutex<Option<ComponentStream>>>,
+}
+
+impl ComponentConnector {
+    pub(crate) fn new(stream: ComponentStream) -> Self {
+        Self {
+            stream: Arc::new(Mutex::new(Some(stream))),
+        }
+    }
+}
+
+impl std::fmt::Debug for ComponentConnector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentConnector").finish()
+    }
+}
+
+impl ServerConnector for ComponentConnector {
+    type Stream = ComponentStream;
+    type Error = tokio_xmpp::Error;
+
+    async fn connect(
+        &self,
+        jid: &Jid,
+        ns: &str,
+    ) -> std::result::Result<XmppStream<Self::Stream>, Self::Error> {
+        let stream = self
+            .stream
+            .lock()
+            .ok()
+            .and_then(|mut stream| stream.take())
+            .ok_or(tokio_xmpp::Error::Disconnected)?;
+        XmppStream::start(stream, jid.clone(), ns.to_owned()).await
+    }
+}
+
+/// Request a STARTTLS upgrade of a fresh stream in namespace `stream_ns` and return the
+/// underlying tcp stream once the server agreed
+pub(crate) async fn negotiate_starttls(
+    stream: TcpStream,
+    jid: &Jid,
+    stream_ns: &str,
+) -> Result<TcpStream> {
+    let mut xmpp_stream = XmppStream::start(stream, jid.clone(), stream_ns.to_owned()).await?;
+    if !xmpp_stream.stream_features.can_starttls() {
+        return Err(TlsHandshakeError::StartTlsNotOffered.into());
+    }
+    xmpp_stream
+        .send(Packet::Stanza(
+            Element::builder("starttls", ns::TLS).build(),
+        ))
+        .await?;
+    loop {
+        match xmpp_stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) if stanza.is("proceed", ns::TLS) => break,
+            Some(Ok(Packet::Text(_))) => {}
+            Some(Err(e)) => return Err(e.into()),
+            _ => return Err(TlsHandshakeError::StartTlsRefused.into()),
+        }
+    }
+    Ok(xmpp_stream.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind_ns_keeps_foreign_payloads() {
+        let iq: Element = "<iq xmlns='jabber:client' type='error' id='a'><pubsub xmlns='http://jabber.org/protocol/pubsub'><publish node='n'/></pubsub><error type='cancel'><item-not-found xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>"
+            .parse()
+            .unwrap();
+        let rebound = rebind_ns(iq, ns::JABBER_CLIENT, ns::COMPONENT_ACCEPT);
+        assert!(rebound.is("iq", ns::COMPONENT_ACCEPT));
+        assert_eq!(rebound.attr("id"), Some("a"));
+        let pubsub = rebound.get_child("pubsub", ns::PUBSUB).unwrap();
+        assert!(pubsub.has_child("publish", ns::PUBSUB));
+        let error = rebound.get_child("error", ns::COMPONENT_ACCEPT).unwrap();
+        assert!(error.has_child("item-not-found", ns::XMPP_STANZAS));
+    }
+}
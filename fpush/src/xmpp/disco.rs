@@ -0,0 +1,133 @@
+use crate::xmpp::adhoc::{AdminCommands, COMMANDS_NS};
+use crate::xmpp::error_messages::{send_error_iq, send_item_not_found_iq};
+use crate::xmpp::reply_queue::ReplySender;
+use fpush_push::FpushPushArc;
+
+use log::{error, info};
+use xmpp::agent::Element;
+use xmpp_parsers::disco::{
+    DiscoInfoQuery, DiscoInfoResult, DiscoItemsQuery, DiscoItemsResult, Feature, Identity, Item,
+};
+use xmpp_parsers::{iq::Iq, jid::Jid};
+
+const FEATURES: [&str; 8] = [
+    "http://jabber.org/protocol/disco#info",
+    "http://jabber.org/protocol/disco#items",
+    "http://jabber.org/protocol/pubsub#publish",
+    "http://jabber.org/protocol/pubsub#publish-options",
+    "urn:xmpp:push:0",
+    "urn:xmpp:ping",
+    "urn:xmpp:serverinfo:0",
+    COMMANDS_NS,
+];
+
+/// Answer disco#info requests for the push service itself or a single push module node
+pub(crate) async fn handle_disco_info(
+    conn: &ReplySender,
+    push_modules: &FpushPushArc,
+    iq_id: &str,
+    iq_payload: Element,
+    from: Jid,
+    to: Jid,
+) {
+    let disco_info_query = match DiscoInfoQuery::try_from(iq_payload) {
+        Ok(query) => query,
+        Err(_) => {
+            send_error_iq(conn, iq_id, from, to).await;
+            return;
+        }
+    };
+    info!("Handling disco info request from: {}", from);
+    let disco_info_result = match disco_info_query.node {
+        None => DiscoInfoResult {
+            node: None,
+            identities: vec![Identity::new("pubsub", "push", "en", "fpush")],
+            features: FEATURES
+                .iter()
+                .map(|feature| Feature::new(*feature))
+                .collect(),
+            extensions: vec![],
+        },
+        Some(module_id) => match push_modules.module_type(&module_id) {
+            Some(module_type) => DiscoInfoResult {
+                node: Some(module_id),
+                identities: vec![Identity::new("pubsub", "push", "en", module_type)],
+                features: vec![Feature::new("urn:xmpp:push:0")],
+                extensions: vec![],
+            },
+            None => {
+                send_item_not_found_iq(conn, iq_id, from, to).await;
+                return;
+            }
+        },
+    };
+    if let Err(e) = conn
+        .send(
+            Iq::from_result(iq_id.to_owned(), Some(disco_info_result))
+                .with_from(to)
+                .with_to(from),
+        )
+        .await
+    {
+        error!(
+            "Could not forward disco info result iq to main handler: {}",
+            e
+        );
+    }
+}
+
+/// List all configured push modules as disco#items nodes
+pub(crate) async fn handle_disco_items(
+    conn: &ReplySender,
+    push_modules: &FpushPushArc,
+    admin_commands: &AdminCommands,
+    iq_id: &str,
+    iq_payload: Element,
+    from: Jid,
+    to: Jid,
+) {
+    let disco_items_query = match DiscoItemsQuery::try_from(iq_payload) {
+        Ok(query) => query,
+        Err(_) => {
+            send_error_iq(conn, iq_id, from, to).await;
+            return;
+        }
+    };
+    match disco_items_query.node.as_deref() {
+        None => {}
+        Some(COMMANDS_NS) => {
+            admin_commands
+                .handle_command_list(conn, iq_id, from, to)
+                .await;
+            return;
+        }
+        Some(_) => {
+            send_item_not_found_iq(conn, iq_id, from, to).await;
+            return;
+        }
+    }
+    info!("Handling disco items request from: {}", from);
+    let items = push_modules
+        .module_ids()
+        .into_iter()
+        .map(|module_id| Item {
+            jid: to.clone(),
+            name: push_modules.module_type(&module_id).map(str::to_string),
+            node: Some(module_id),
+        })
+        .collect();
+    let disco_items_result = DiscoItemsResult { node: None, items };
+    if let Err(e) = conn
+        .send(
+            Iq::from_result(iq_id.to_owned(), Some(disco_items_result))
+                .with_from(to)
+                .with_to(from),
+        )
+        .await
+    {
+        error!(
+            "Could not forward disco items result iq to main handler: {}",
+            e
+        );
+    }
+}
@@ -1,10 +1,17 @@
-use log::error;
-use tokio::sync::mpsc;
+use crate::xmpp::reply_queue::ReplySender;
+use fpush_push::{PushRequestError, ReplyAction, ReplyErrorCondition, ReplyErrorType, ReplyPolicy};
+
+use log::{debug, error};
+use xmpp::agent::Element;
 use xmpp::jid::Jid;
-use xmpp_parsers::{iq::Iq, stanza_error::StanzaError};
+use xmpp_parsers::iq::Iq;
+use xmpp_parsers::stanza_error::{DefinedCondition, ErrorType, StanzaError};
+
+/// namespace of the application error element naming the outcome of a push request
+const FPUSH_ERRORS_NS: &str = "urn:fpush:errors:0";
 
 #[inline(always)]
-pub async fn send_ack_iq(conn: &mpsc::Sender<Iq>, id: &str, jid: Jid, from: Jid) {
+pub async fn send_ack_iq(conn: &ReplySender, id: &str, jid: Jid, from: Jid) {
     if let Err(e) = conn
         .send(Iq::empty_result(jid, (*id).to_string()).with_from(from))
         .await
@@ -13,11 +20,81 @@ pub async fn send_ack_iq(conn: &mpsc::Sender<Iq>, id: &str, jid: Jid, from: Jid)
     }
 }
 
+/// Reply to a failed push request as configured in the reply policy of the push module
+pub async fn send_push_error_iq(
+    conn: &ReplySender,
+    id: &str,
+    jid: Jid,
+    from: Jid,
+    error: &PushRequestError,
+    reply_policy: &ReplyPolicy,
+) {
+    let (error_type, condition, text) = match reply_policy.action(error) {
+        ReplyAction::Ack => {
+            send_ack_iq(conn, id, jid, from).await;
+            return;
+        }
+        ReplyAction::Error {
+            error_type,
+            condition,
+            text,
+        } => (error_type, condition, text),
+    };
+    let mut error_stanza = StanzaError::new(
+        stanza_error_type(error_type),
+        stanza_error_condition(condition),
+        "en",
+        text.as_deref().unwrap_or(error.description()),
+    );
+    if *reply_policy.application_errors() {
+        error_stanza.other = Some(Element::builder(error.reason(), FPUSH_ERRORS_NS).build());
+    }
+    if let Err(e) = conn
+        .send(
+            Iq::from_error((*id).to_string(), error_stanza)
+                .with_to(jid)
+                .with_from(from),
+        )
+        .await
+    {
+        error!("Could not forward outgoing iq to main handler: {}", e);
+    }
+}
+
+fn stanza_error_type(error_type: ReplyErrorType) -> ErrorType {
+    match error_type {
+        ReplyErrorType::Auth => ErrorType::Auth,
+        ReplyErrorType::Cancel => ErrorType::Cancel,
+        ReplyErrorType::Continue => ErrorType::Continue,
+        ReplyErrorType::Modify => ErrorType::Modify,
+        ReplyErrorType::Wait => ErrorType::Wait,
+    }
+}
+
+fn stanza_error_condition(condition: ReplyErrorCondition) -> DefinedCondition {
+    match condition {
+        ReplyErrorCondition::BadRequest => DefinedCondition::BadRequest,
+        ReplyErrorCondition::Conflict => DefinedCondition::Conflict,
+        ReplyErrorCondition::FeatureNotImplemented => DefinedCondition::FeatureNotImplemented,
+        ReplyErrorCondition::Forbidden => DefinedCondition::Forbidden,
+        ReplyErrorCondition::InternalServerError => DefinedCondition::InternalServerError,
+        ReplyErrorCondition::ItemNotFound => DefinedCondition::ItemNotFound,
+        ReplyErrorCondition::NotAcceptable => DefinedCondition::NotAcceptable,
+        ReplyErrorCondition::NotAllowed => DefinedCondition::NotAllowed,
+        ReplyErrorCondition::NotAuthorized => DefinedCondition::NotAuthorized,
+        ReplyErrorCondition::PolicyViolation => DefinedCondition::PolicyViolation,
+        ReplyErrorCondition::ResourceConstraint => DefinedCondition::ResourceConstraint,
+        ReplyErrorCondition::ServiceUnavailable => DefinedCondition::ServiceUnavailable,
+        ReplyErrorCondition::UndefinedCondition => DefinedCondition::UndefinedCondition,
+        ReplyErrorCondition::UnexpectedRequest => DefinedCondition::UnexpectedRequest,
+    }
+}
+
 #[inline(always)]
-pub async fn send_error_policy_iq(conn: &mpsc::Sender<Iq>, id: &str, jid: Jid, from: Jid) {
+pub async fn send_error_iq(conn: &ReplySender, id: &str, jid: Jid, from: Jid) {
     let error_stanza = StanzaError::new(
         xmpp_parsers::stanza_error::ErrorType::Cancel,
-        xmpp_parsers::stanza_error::DefinedCondition::PolicyViolation,
+        xmpp_parsers::stanza_error::DefinedCondition::BadRequest,
         "en",
         "A error occured",
     );
@@ -34,12 +111,12 @@ pub async fn send_error_policy_iq(conn: &mpsc::Sender<Iq>, id: &str, jid: Jid, f
 }
 
 #[inline(always)]
-pub async fn send_error_iq(conn: &mpsc::Sender<Iq>, id: &str, jid: Jid, from: Jid) {
+pub async fn send_bad_request_iq(conn: &ReplySender, id: &str, jid: Jid, from: Jid, reason: &str) {
     let error_stanza = StanzaError::new(
-        xmpp_parsers::stanza_error::ErrorType::Cancel,
+        xmpp_parsers::stanza_error::ErrorType::Modify,
         xmpp_parsers::stanza_error::DefinedCondition::BadRequest,
         "en",
-        "A error occured",
+        reason,
     );
     if let Err(e) = conn
         .send(
@@ -54,17 +131,32 @@ pub async fn send_error_iq(conn: &mpsc::Sender<Iq>, id: &str, jid: Jid, from: Ji
 }
 
 #[inline(always)]
-pub async fn send_wait_iq_reason_old_prosody(
-    conn: &mpsc::Sender<Iq>,
-    id: &str,
-    jid: Jid,
-    from: Jid,
-) {
+pub async fn send_item_not_found_iq(conn: &ReplySender, id: &str, jid: Jid, from: Jid) {
     let error_stanza = StanzaError::new(
-        xmpp_parsers::stanza_error::ErrorType::Wait,
-        xmpp_parsers::stanza_error::DefinedCondition::BadRequest,
+        xmpp_parsers::stanza_error::ErrorType::Cancel,
+        xmpp_parsers::stanza_error::DefinedCondition::ItemNotFound,
+        "en",
+        "Unknown node",
+    );
+    if let Err(e) = conn
+        .send(
+            Iq::from_error((*id).to_string(), error_stanza)
+                .with_to(jid)
+                .with_from(from),
+        )
+        .await
+    {
+        error!("Could not forward outgoing iq to main handler: {}", e);
+    }
+}
+
+#[inline(always)]
+pub async fn send_forbidden_iq(conn: &ReplySender, id: &str, jid: Jid, from: Jid) {
+    let error_stanza = StanzaError::new(
+        xmpp_parsers::stanza_error::ErrorType::Auth,
+        xmpp_parsers::stanza_error::DefinedCondition::Forbidden,
         "en",
-        "Invalid push format, update your prosody community modules (debian stable has a bug, use backports instead)",
+        "Not authorized",
     );
     if let Err(e) = conn
         .send(
@@ -77,3 +169,31 @@ pub async fn send_wait_iq_reason_old_prosody(
         error!("Could not forward outgoing iq to main handler: {}", e);
     }
 }
+
+/// Reject a request iq without blocking the main loop while fpush is overloaded
+pub fn send_resource_constraint_iq(conn: &ReplySender, stanza: &Element) {
+    if stanza.name() != "iq" || !matches!(stanza.attr("type"), Some("get") | Some("set")) {
+        return;
+    }
+    let (id, jid, from) = match (
+        stanza.attr("id"),
+        stanza.attr("from").map(Jid::new),
+        stanza.attr("to").map(Jid::new),
+    ) {
+        (Some(id), Some(Ok(jid)), Some(Ok(from))) => (id, jid, from),
+        _ => return,
+    };
+    let error_stanza = StanzaError::new(
+        xmpp_parsers::stanza_error::ErrorType::Wait,
+        xmpp_parsers::stanza_error::DefinedCondition::ResourceConstraint,
+        "en",
+        "Push server overloaded, try again later",
+    );
+    if let Err(e) = conn.try_send(
+        Iq::from_error(id.to_string(), error_stanza)
+            .with_to(jid)
+            .with_from(from),
+    ) {
+        debug!("Could not queue resource-constraint reply: {}", e);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::config::fpush_config::{ConnectionMode, FpushComponentSettings};
+use crate::error::{Error, Result};
+
+use log::{debug, warn};
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use xmpp::agent::Element;
+use xmpp_parsers::{iq::Iq, jid::Jid, ping::Ping};
+
+const PING_ID_PREFIX: &str = "fpush-ping-";
+
+/// XEP-0199 pings sent to the XMPP server to detect dead connections
+pub(crate) struct Keepalive {
+    interval: Interval,
+    max_missed_pongs: u32,
+    /// None for client connections, the server sets our bound JID
+    from: Option<Jid>,
+    to: Jid,
+    ping_counter: u64,
+    /// id of the ping still waiting for its pong
+    outstanding_ping: Option<String>,
+    missed_pongs: u32,
+}
+
+pub(crate) enum KeepaliveAction {
+    SendPing(Iq),
+    ConnectionDead,
+}
+
+impl Keepalive {
+    /// Returns None if keepalive pings are not configured
+    pub(crate) fn new(component: &FpushComponentSettings) -> Result<Option<Self>> {
+        let settings = match component.keepalive() {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+        let target = settings
+            .target()
+            .as_deref()
+            .unwrap_or(component.server_hostname());
+        let period = *settings.interval();
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Ok(Some(Self {
+            interval,
+            max_missed_pongs: *settings.max_missed_pongs(),
+            from: match component.mode() {
+                ConnectionMode::Component => Some(parse_jid(component.component_hostname())?),
+                ConnectionMode::Client => None,
+            },
+            to: parse_jid(target)?,
+            ping_counter: 0,
+            outstanding_ping: None,
+            missed_pongs: 0,
+        }))
+    }
+
+    /// Wait until the next ping is due
+    pub(crate) async fn tick(&mut self) {
+        self.interval.tick().await;
+    }
+
+    /// Create the next ping or report the connection as dead after too many missed pongs
+    pub(crate) fn next_action(&mut self) -> KeepaliveAction {
+        if self.outstanding_ping.is_some() {
+            self.missed_pongs += 1;
+            warn!(
+                "Missed pong {}/{} from {}",
+                self.missed_pongs, self.max_missed_pongs, self.to
+            );
+            if self.missed_pongs >= self.max_missed_pongs {
+                return KeepaliveAction::ConnectionDead;
+            }
+        }
+        self.ping_counter += 1;
+        let id = format!("{}{}", PING_ID_PREFIX, self.ping_counter);
+        self.outstanding_ping = Some(id.clone());
+        let mut ping = Iq::from_get(id, Ping).with_to(self.to.clone());
+        ping.from = self.from.clone();
+        KeepaliveAction::SendPing(ping)
+    }
+
+    /// Consume the reply to the outstanding ping. Returns true if the stanza was that pong
+    pub(crate) fn handle_pong(&mut self, stanza: &Element) -> bool {
+        if stanza.name() != "iq"
+            || !matches!(stanza.attr("type"), Some("result") | Some("error"))
+            || !self
+                .outstanding_ping
+                .as_deref()
+                .is_some_and(|ping| stanza.attr("id") == Some(ping))
+            || !stanza
+                .attr("from")
+                .and_then(|from| Jid::new(from).ok())
+                .is_some_and(|from| from == self.to)
+        {
+            return false;
+        }
+        // an error reply still proves that the connection is alive
+        debug!("Received pong from {}", self.to);
+        self.outstanding_ping = None;
+        self.missed_pongs = 0;
+        true
+    }
+}
+
+fn parse_jid(jid: &str) -> Result<Jid> {
+    Jid::new(jid).map_err(|e| Error::Config(format!("Invalid keepalive JID {}: {}", jid, e)))
+}
+
+/// Wait for the next keepalive tick. Never completes if keepalive pings are disabled
+pub(crate) async fn keepalive_tick(keepalive: &mut Option<Keepalive>) {
+    match keepalive {
+        Some(keepalive) => keepalive.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keepalive(max_missed_pongs: u32) -> Keepalive {
+        let component: FpushComponentSettings = serde_json::from_value(serde_json::json!({
+            "componentHostname": "push.example.org",
+            "componentKey": "key",
+            "serverHostname": "example.org",
+            "serverPort": 5347,
+            "keepalive": { "interval": "30s", "maxMissedPongs": max_missed_pongs }
+        }))
+        .unwrap();
+        Keepalive::new(&component).unwrap().unwrap()
+    }
+
+    fn ping_id(action: KeepaliveAction) -> String {
+        match action {
+            KeepaliveAction::SendPing(ping) => ping.id,
+            KeepaliveAction::ConnectionDead => panic!("connection reported as dead"),
+        }
+    }
+
+    fn pong(id: &str) -> Element {
+        format!("<iq xmlns='jabber:client' type='result' id='{}' from='example.org' to='push.example.org'/>", id)
+            .parse()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn dead_after_missed_pongs() {
+        let mut keepalive = keepalive(2);
+        assert_eq!(ping_id(keepalive.next_action()), "fpush-ping-1");
+        assert_eq!(ping_id(keepalive.next_action()), "fpush-ping-2");
+        assert_eq!(keepalive.missed_pongs, 1);
+        assert!(matches!(
+            keepalive.next_action(),
+            KeepaliveAction::ConnectionDead
+        ));
+    }
+
+    #[tokio::test]
+    async fn pong_resets_missed_pongs() {
+        let mut keepalive = keepalive(2);
+        ping_id(keepalive.next_action());
+        let id = ping_id(keepalive.next_action());
+        assert_eq!(keepalive.missed_pongs, 1);
+        assert!(keepalive.handle_pong(&pong(&id)));
+        assert_eq!(keepalive.missed_pongs, 0);
+        assert!(keepalive.outstanding_ping.is_none());
+        // the next ping is not counted as missed
+        ping_id(keepalive.next_action());
+        assert_eq!(keepalive.missed_pongs, 0);
+    }
+
+    #[tokio::test]
+    async fn ignores_other_stanzas() {
+        let mut keepalive = keepalive(2);
+        ping_id(keepalive.next_action());
+        assert!(!keepalive.handle_pong(&pong("some-request")));
+        let ping_request: Element = "<iq xmlns='jabber:client' type='get' id='fpush-ping-1'><ping xmlns='urn:xmpp:ping'/></iq>"
+            .parse()
+            .unwrap();
+        assert!(!keepalive.handle_pong(&ping_request));
+        let message: Element = "<message xmlns='jabber:client' id='fpush-ping-1'/>"
+            .parse()
+            .unwrap();
+        assert!(!keepalive.handle_pong(&message));
+        assert!(keepalive.outstanding_ping.is_some());
+    }
+
+    #[tokio::test]
+    async fn accepts_only_outstanding_pong() {
+        let mut keepalive = keepalive(3);
+        let stale = ping_id(keepalive.next_action());
+        let id = ping_id(keepalive.next_action());
+        assert!(!keepalive.handle_pong(&pong(&stale)));
+        let foreign: Element = format!(
+            "<iq xmlns='jabber:client' type='result' id='{}' from='user@example.org/res' to='push.example.org'/>",
+            id
+        )
+        .parse()
+        .unwrap();
+        assert!(!keepalive.handle_pong(&foreign));
+        assert_eq!(keepalive.missed_pongs, 1);
+        assert!(keepalive.handle_pong(&pong(&id)));
+        assert_eq!(keepalive.missed_pongs, 0);
+    }
+}
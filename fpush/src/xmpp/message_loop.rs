@@ -1,92 +1,218 @@
-use crate::config::fpush_config::FpushConfig;
-use crate::xmpp::error_messages::send_wait_iq_reason_old_prosody;
+use std::sync::Arc;
+
+use crate::config::fpush_config::{ComponentTlsMode, ConnectionMode, FpushComponentSettings};
+use crate::xmpp::adhoc::COMMANDS_NS;
+use crate::xmpp::client_login::login_client;
+use crate::xmpp::connector::{
+    negotiate_starttls, ComponentConnector, ComponentEndpoint, ComponentStream, XmppConnection,
+};
+use crate::xmpp::disco::{handle_disco_info, handle_disco_items};
+use crate::xmpp::keepalive::{keepalive_tick, Keepalive, KeepaliveAction};
+use crate::xmpp::publish_options::PublishOptions;
+use crate::xmpp::push_summary::parse_push_summary;
+use crate::xmpp::reply_queue::{ReplyQueue, ReplySender};
+use crate::xmpp::request_context::RequestContext;
+use crate::xmpp::server_status::ServerStatus;
+use crate::xmpp::tls::ComponentTlsConnector;
+use crate::xmpp::unregister::parse_unregister_request;
 use crate::{
     error::{Error, Result},
-    xmpp::error_messages::{send_ack_iq, send_error_iq, send_error_policy_iq},
+    xmpp::error_messages::{
+        send_ack_iq, send_bad_request_iq, send_error_iq, send_forbidden_iq, send_push_error_iq,
+        send_resource_constraint_iq,
+    },
 };
-use fpush_push::{FpushPushArc, PushRequestError, PushRequestResult};
+use fpush_push::{PushRequest, PushRequestError};
 
 use futures::{SinkExt, StreamExt};
 use log::{debug, error, info, warn};
 
-use tokio::sync::mpsc;
-use tokio_xmpp::connect::{DnsConfig, TcpServerConnector};
+use tokio::sync::watch;
 use tokio_xmpp::Component;
 use xmpp::agent::Element;
-use xmpp_parsers::disco::DiscoInfoResult;
-use xmpp_parsers::{iq::Iq, jid::Jid, pubsub::PubSub};
+use xmpp_parsers::{iq::Iq, jid::Jid, ns, pubsub::PubSub};
 
 pub(crate) async fn init_component_connection(
-    config: &FpushConfig,
-) -> Result<Component<TcpServerConnector>> {
-    let component = Component::new_plaintext(
-        config.component().component_hostname(),
-        config.component().component_key(),
-        DnsConfig::no_srv(
-            config.component().server_hostname(),
-            *config.component().server_port(),
-        ),
-    )
-    .await?;
+    component_config: &FpushComponentSettings,
+    endpoint: &ComponentEndpoint,
+    tls: Option<&ComponentTlsConnector>,
+) -> Result<XmppConnection> {
+    let stream_ns = match component_config.mode() {
+        ConnectionMode::Component => ns::COMPONENT_ACCEPT,
+        ConnectionMode::Client => ns::JABBER_CLIENT,
+    };
+    let tcp_stream = endpoint.connect().await?;
 
-    Ok(component)
+    let stream = match tls {
+        None => ComponentStream::Plain(tcp_stream),
+        Some(tls) => {
+            let tcp_stream = match tls.mode() {
+                ComponentTlsMode::DirectTls => tcp_stream,
+                ComponentTlsMode::StartTls => {
+                    let jid = Jid::new(component_config.local_jid())
+                        .map_err(|e| Error::Config(format!("Invalid JID: {}", e)))?;
+                    negotiate_starttls(tcp_stream, &jid, stream_ns).await?
+                }
+            };
+            ComponentStream::Tls(Box::new(tls.handshake(tcp_stream).await?))
+        }
+    };
+
+    match component_config.mode() {
+        ConnectionMode::Component => {
+            let component = Component::new_with_connector(
+                component_config.component_hostname(),
+                component_config.component_key(),
+                ComponentConnector::new(stream),
+            )
+            .await?;
+            Ok(XmppConnection::Component(component))
+        }
+        ConnectionMode::Client => {
+            let jid = Jid::new(component_config.local_jid())
+                .map_err(|e| Error::Config(format!("Invalid client JID: {}", e)))?;
+            let password = component_config.password().as_deref().unwrap_or_default();
+            let client = login_client(stream, jid, password).await?;
+            Ok(XmppConnection::Client(Box::new(client)))
+        }
+    }
 }
 
 #[inline(always)]
 pub(crate) async fn message_loop_main_thread(
-    mut conn: tokio_xmpp::Component<TcpServerConnector>,
-    push_modules: FpushPushArc,
+    mut conn: XmppConnection,
+    component_config: &FpushComponentSettings,
+    context: Arc<RequestContext>,
+    status: Arc<ServerStatus>,
+    replies: &mut ReplyQueue,
+    shutdown: &mut watch::Receiver<bool>,
 ) {
-    // #[cfg(feature = "random_delay_before_push")]
-    //let mut rng = rand::thread_rng();
+    let mut keepalive = match Keepalive::new(component_config) {
+        Ok(keepalive) => keepalive,
+        Err(e) => {
+            error!("{}: Disabling keepalive pings: {}", status.name(), e);
+            None
+        }
+    };
 
-    let (out_sender, mut out_recv) = mpsc::channel::<Iq>(3000);
     loop {
         tokio::select! {
-            xmpp_msg = out_recv.recv() => {
+            xmpp_msg = replies.recv() => {
                 if let Some(msg) = xmpp_msg {
-                    if let Err(e) = conn.send(msg.into()).await {
-                        error!("Could not reply iq: {}", e);
+                    if !replies.send(msg, &mut conn, &status).await {
+                        error!("{}: Could not write replies, opening new connection", status.name());
+                        return;
                     }
                 } else {
-                    error!("Connection closed");
+                    error!("{}: Connection closed", status.name());
                     return;
                 }
             }
             xmpp_poll = conn.next() => {
                 match xmpp_poll {
                     Some(stanza) => {
-                        dispatch_xmpp_msg_to_thread(&out_sender, push_modules.clone(), stanza);
+                        status.stanza_received();
+                        if let Some(keepalive) = keepalive.as_mut() {
+                            if keepalive.handle_pong(&stanza) {
+                                continue;
+                            }
+                        }
+                        dispatch_xmpp_msg_to_thread(
+                            replies.sender(),
+                            context.clone(),
+                            status.clone(),
+                            stanza,
+                        );
                     },
                     None => {
-                        error!("The stream was closed, opening new connection");
+                        error!("{}: The stream was closed, opening new connection", status.name());
                         return;
                     }
                 }
             },
+            _ = keepalive_tick(&mut keepalive) => {
+                if let Some(keepalive) = keepalive.as_mut() {
+                    match keepalive.next_action() {
+                        KeepaliveAction::SendPing(ping) => {
+                            if let Err(e) = conn.send(ping.into()).await {
+                                error!("{}: Could not send keepalive ping: {}", status.name(), e);
+                            }
+                        }
+                        KeepaliveAction::ConnectionDead => {
+                            error!(
+                                "{}: Server stopped answering keepalive pings, opening new connection",
+                                status.name()
+                            );
+                            return;
+                        }
+                    }
+                }
+            },
+            _ = shutdown.changed() => {
+                info!(
+                    "{}: Shutting down, waiting for {} requests in flight",
+                    status.name(),
+                    status.in_flight_requests()
+                );
+                replies.drain(&mut conn, &status).await;
+                match conn.close().await {
+                    Ok(()) => info!("{}: Closed XMPP stream", status.name()),
+                    Err(e) => error!("{}: Could not close XMPP stream: {}", status.name(), e),
+                }
+                return;
+            },
             else => {
-                error!("Main loop error: Closing");
+                error!("{}: Main loop error: Closing", status.name());
                 return;
             }
         };
     }
-    // TODO: batch out iq's together send every xxxms out
 }
 
 #[inline(always)]
 fn dispatch_xmpp_msg_to_thread(
-    conn: &mpsc::Sender<Iq>,
-    push_modules: FpushPushArc,
+    conn: &ReplySender,
+    context: Arc<RequestContext>,
+    status: Arc<ServerStatus>,
     stanza: Element,
 ) {
+    // disco, ad-hoc commands, pings and unregister requests never wait for a push backend
+    let request_slot = if is_pubsub_publish(&stanza) {
+        match status.try_acquire_request_slot() {
+            Some(request_slot) => Some(request_slot),
+            None => {
+                send_resource_constraint_iq(conn, &stanza);
+                return;
+            }
+        }
+    } else {
+        None
+    };
     let conn_to_master = conn.clone();
     tokio::spawn(async move {
-        handle_iq(&conn_to_master, push_modules, stanza).await;
+        let _request_slot = request_slot;
+        handle_iq(&conn_to_master, &context, &status, stanza).await;
     });
 }
 
+/// True for iq stanzas publishing to a pubsub node, i.e. push requests
+fn is_pubsub_publish(stanza: &Element) -> bool {
+    stanza.name() == "iq"
+        && stanza.attr("type") == Some("set")
+        && stanza
+            .get_child("pubsub", ns::PUBSUB)
+            .is_some_and(|pubsub| pubsub.has_child("publish", ns::PUBSUB))
+}
+
 #[inline(always)]
-async fn handle_iq(conn: &mpsc::Sender<Iq>, push_modules: FpushPushArc, stanza: Element) {
+async fn handle_iq(
+    conn: &ReplySender,
+    context: &RequestContext,
+    status: &ServerStatus,
+    stanza: Element,
+) {
+    let push_modules = context.push_modules();
+    let admin_commands = context.admin_commands();
     // parse message
     match Iq::try_from(stanza) {
         Err(e) => {
@@ -95,38 +221,28 @@ async fn handle_iq(conn: &mpsc::Sender<Iq>, push_modules: FpushPushArc, stanza:
         Ok(iq) => {
             let (to, from, iq_payload) = match (iq.to, iq.from, iq.payload) {
                 (Some(to), Some(from), xmpp_parsers::iq::IqType::Set(iq_payload)) => {
+                    if iq_payload.is("command", COMMANDS_NS) {
+                        admin_commands
+                            .handle_command(conn, context, &iq.id, iq_payload, from, to)
+                            .await;
+                        return;
+                    }
                     (to, from, iq_payload)
                 }
                 (Some(to), Some(from), xmpp_parsers::iq::IqType::Get(iq_payload)) => {
-                    if iq_payload.name() == "query" {
-                        // handle disco
-                        if xmpp_parsers::disco::DiscoInfoQuery::try_from(iq_payload).is_err() {
-                            send_error_iq(conn, &iq.id, from, to).await;
-                            return;
-                        }
-                        info!("Handling disco info request from: {}", from);
-                        // handle disco
-                        let disco_info_result = DiscoInfoResult {
-                            node: None,
-                            identities: vec![],
-                            features: vec![xmpp_parsers::disco::Feature::new(
-                                "urn:xmpp:serverinfo:0",
-                            )],
-                            extensions: vec![],
-                        };
-                        if let Err(e) = conn
-                            .send(
-                                Iq::from_result(iq.id.to_owned(), Some(disco_info_result))
-                                    .with_from(to)
-                                    .with_to(from),
-                            )
-                            .await
-                        {
-                            error!(
-                                "Could not forward disco info result iq to main handler: {}",
-                                e
-                            );
-                        }
+                    if iq_payload.is("query", ns::DISCO_INFO) {
+                        handle_disco_info(conn, push_modules, &iq.id, iq_payload, from, to).await;
+                    } else if iq_payload.is("query", ns::DISCO_ITEMS) {
+                        handle_disco_items(
+                            conn,
+                            push_modules,
+                            admin_commands,
+                            &iq.id,
+                            iq_payload,
+                            from,
+                            to,
+                        )
+                        .await;
                     } else if iq_payload.name() == "ping" {
                         info!("Received ping from {}", from);
                         send_ack_iq(conn, &iq.id, from, to).await;
@@ -148,101 +264,146 @@ async fn handle_iq(conn: &mpsc::Sender<Iq>, push_modules: FpushPushArc, stanza:
                     return;
                 }
             };
-            let (module_id, token) = match parse_token_and_module_id(iq_payload) {
-                Ok((module_id, token)) => (module_id, token),
+            if !context.origin_filter().is_allowed(from.domain().as_str()) {
+                warn!(
+                    "Rejecting push request from denied domain {}",
+                    from.domain()
+                );
+                send_forbidden_iq(conn, &iq.id, from, to).await;
+                return;
+            }
+            if let Some(request) = parse_unregister_request(&iq_payload, &from) {
+                let (module_id, request) = match request {
+                    Ok(request) => request,
+                    Err(e) => {
+                        warn!("Rejecting unregister request from {}: {}", from, e);
+                        send_bad_request_iq(conn, &iq.id, from, to, &e.to_string()).await;
+                        return;
+                    }
+                };
+                info!(
+                    "{}: Unregister request for token {} from {}",
+                    module_id,
+                    request.token(),
+                    from
+                );
+                match push_modules.unregister_token(&module_id, &request) {
+                    Ok(()) => send_ack_iq(conn, &iq.id, from, to).await,
+                    Err(e) => {
+                        log_push_error(&module_id, request.token(), &from, &e);
+                        let reply_policy = push_modules.reply_policy(&module_id);
+                        send_push_error_iq(conn, &iq.id, from, to, &e, &reply_policy).await;
+                    }
+                }
+                return;
+            }
+            let (module_id, push_request) = match parse_push_request(iq_payload, &from) {
+                Ok((module_id, push_request)) => (module_id, push_request),
                 Err(e) => {
-                    warn!(
-                        "Could not retrieve token or module_id: {} source: {}",
-                        e, from
-                    );
-                    send_wait_iq_reason_old_prosody(conn, &iq.id, from, to).await;
+                    warn!("Rejecting push request from {}: {}", from, e);
+                    send_bad_request_iq(conn, &iq.id, from, to, &e.to_string()).await;
                     return;
                 }
             };
+            let token = push_request.token();
             debug!(
                 "Selected push_module {} for JID {} with token {}",
                 module_id, from, token
             );
             // handle_push_request
-            let push_result = push_modules.push(&module_id, token.clone()).await;
-            handle_push_result(conn, &module_id, &token, &push_result, from, to, iq.id).await
+            let push_result = push_modules.push(&module_id, &push_request).await;
+            status.push_handled(push_result.is_ok());
+            match push_result {
+                Ok(()) => send_ack_iq(conn, &iq.id, from, to).await,
+                Err(e) => {
+                    log_push_error(&module_id, token, &from, &e);
+                    let reply_policy = push_modules.reply_policy(&module_id);
+                    send_push_error_iq(conn, &iq.id, from, to, &e, &reply_policy).await;
+                }
+            }
         }
     }
 }
 
-async fn handle_push_result(
-    conn: &mpsc::Sender<Iq>,
-    module_id: &str,
-    token: &str,
-    push_result: &PushRequestResult<()>,
-    from: Jid,
-    to: Jid,
-    iq_id: String,
-) {
-    match push_result {
-        Ok(()) => send_ack_iq(conn, &iq_id, from, to).await,
-        Err(PushRequestError::TokenRatelimited) => {
-            // Some admins did not understood the wait_iq -> we know send an ack
-            send_ack_iq(conn, &iq_id, from, to).await
+fn log_push_error(module_id: &str, token: &str, from: &Jid, error: &PushRequestError) {
+    match error {
+        PushRequestError::TokenRatelimited => {}
+        PushRequestError::DomainRatelimited => {
+            warn!(
+                "{}: Domain limit exceeded by {}, dropping push request for token {}",
+                module_id,
+                from.domain(),
+                token
+            );
         }
-        Err(PushRequestError::TokenBlocked) => {
+        PushRequestError::TokenBlocked => {
             warn!(
                 "{}: Received push request from blocked token {} from {}",
                 module_id, token, from
             );
-            send_error_policy_iq(conn, &iq_id, from, to).await;
         }
-        Err(PushRequestError::Internal) => {
+        PushRequestError::Internal => {
             warn!(
                 "{}: Incountered internal push error for token {} from {}",
                 module_id, token, from
             );
-            send_error_iq(conn, &iq_id, from, to).await;
         }
-        Err(PushRequestError::UnknownPushModule) => {
+        PushRequestError::TokenUnregistered => {
+            debug!(
+                "{}: Dropped push request for unregistered token {} from {}",
+                module_id, token, from
+            );
+        }
+        PushRequestError::NotAuthorized => {
+            warn!(
+                "{}: Rejected push request without valid secret for token {} from {}",
+                module_id, token, from
+            );
+        }
+        PushRequestError::UnknownPushModule => {
             warn!(
                 "{}: Unknown push module requested for token {} from {}",
                 module_id, token, from
             );
-            send_error_iq(conn, &iq_id, from, to).await;
+        }
+        PushRequestError::CircuitOpen => {
+            debug!(
+                "{}: Push backend unavailable, rejecting push request for token {} from {}",
+                module_id, token, from
+            );
+        }
+        PushRequestError::ModuleUnavailable => {
+            warn!(
+                "{}: Push module failed to load, rejecting push request for token {} from {}",
+                module_id, token, from
+            );
         }
     }
 }
 
 #[inline(always)]
-fn parse_token_and_module_id(iq_payload: Element) -> Result<(String, String)> {
-    if let Ok(pubsub) = PubSub::try_from(iq_payload) {
-        match pubsub {
-            PubSub::Publish {
-                publish: pubsub_payload,
-                publish_options: None,
-            } => Ok(("default".to_string(), pubsub_payload.node.0)),
-            PubSub::Publish {
-                publish: pubsub_payload,
-                publish_options: Some(publish_options),
-            } => {
-                if let Some(data_forms) = publish_options.form {
-                    if data_forms.fields.len() > 5 {
-                        return Err(Error::PubSubToManyPublishOptions);
-                    }
-                    for field in &data_forms.fields {
-                        match field.var {
-                            Some(ref field_var_name) if field_var_name == "pushModule" => {
-                                if let Some(push_module_id) = field.values.first() {
-                                    return Ok((push_module_id.to_string(), pubsub_payload.node.0));
-                                }
-                            }
-                            None | Some(_) => {
-                                return Err(Error::PubSubInvalidPushModuleConfiguration);
-                            }
-                        }
-                    }
-                }
-                Ok(("default".to_string(), pubsub_payload.node.0))
-            }
-            _ => Err(Error::PubSubNonPublish),
-        }
-    } else {
-        Err(Error::PubSubInvalidFormat)
-    }
+fn parse_push_request(iq_payload: Element, from: &Jid) -> Result<(String, PushRequest)> {
+    let (publish, publish_options) = match PubSub::try_from(iq_payload) {
+        Ok(PubSub::Publish {
+            publish,
+            publish_options,
+        }) => (publish, publish_options),
+        Ok(_) => return Err(Error::PubSubNonPublish),
+        Err(_) => return Err(Error::PubSubInvalidFormat),
+    };
+    let publish_options =
+        PublishOptions::parse(publish_options.and_then(|publish_options| publish_options.form))?;
+    let summary = parse_push_summary(&publish);
+    let module_id = publish_options
+        .push_module
+        .unwrap_or_else(|| "default".to_string());
+    Ok((
+        module_id,
+        PushRequest::new(
+            publish.node.0,
+            from.domain().to_string(),
+            summary,
+            publish_options.options,
+        ),
+    ))
 }
@@ -1,3 +1,24 @@
+mod adhoc;
+pub(crate) use adhoc::AdminCommands;
+mod client_login;
+mod connector;
+pub(crate) use connector::ComponentEndpoint;
+mod disco;
 mod message_loop;
 pub(crate) use message_loop::{init_component_connection, message_loop_main_thread};
 mod error_messages;
+mod keepalive;
+mod origin_filter;
+pub(crate) use origin_filter::OriginFilter;
+mod publish_options;
+mod push_summary;
+mod reply_batch;
+mod reply_queue;
+pub(crate) use reply_queue::ReplyQueue;
+mod request_context;
+pub(crate) use request_context::RequestContext;
+mod server_status;
+pub(crate) use server_status::ServerStatus;
+mod tls;
+pub(crate) use tls::ComponentTlsConnector;
+mod unregister;
@@ -0,0 +1,141 @@
+use std::sync::RwLock;
+
+use crate::config::fpush_config::OriginDomainSettings;
+use crate::error::{Error, Result};
+
+/// Exact domain or wildcard matching all subdomains of a domain
+#[derive(Debug, PartialEq, Eq)]
+enum DomainPattern {
+    Exact(String),
+    /// `*.example.org` stored as `.example.org`
+    Subdomains(String),
+}
+
+impl DomainPattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        let invalid = || Error::Config(format!("Invalid origin domain pattern {}", pattern));
+        match pattern.strip_prefix("*.") {
+            Some(domain) if !domain.is_empty() && !domain.contains('*') => {
+                Ok(DomainPattern::Subdomains(format!(".{}", domain)))
+            }
+            Some(_) => Err(invalid()),
+            None if !pattern.is_empty() && !pattern.contains('*') => {
+                Ok(DomainPattern::Exact(pattern))
+            }
+            None => Err(invalid()),
+        }
+    }
+
+    #[inline(always)]
+    fn matches(&self, domain: &str) -> bool {
+        match self {
+            DomainPattern::Exact(pattern) => pattern == domain,
+            DomainPattern::Subdomains(suffix) => domain.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct OriginRules {
+    allow: Vec<DomainPattern>,
+    deny: Vec<DomainPattern>,
+}
+
+impl OriginRules {
+    fn new(settings: &OriginDomainSettings) -> Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| DomainPattern::parse(pattern))
+                .collect::<Result<Vec<DomainPattern>>>()
+        };
+        Ok(Self {
+            allow: parse(settings.allow())?,
+            deny: parse(settings.deny())?,
+        })
+    }
+}
+
+/// Allowlist and denylist of origin domains, replaceable at runtime
+pub(crate) struct OriginFilter {
+    rules: RwLock<OriginRules>,
+}
+
+impl OriginFilter {
+    pub(crate) fn new(settings: &OriginDomainSettings) -> Result<Self> {
+        Ok(Self {
+            rules: RwLock::new(OriginRules::new(settings)?),
+        })
+    }
+
+    /// Replace both lists. On error the current lists are kept
+    pub(crate) fn reload(&self, settings: &OriginDomainSettings) -> Result<()> {
+        let rules = OriginRules::new(settings)?;
+        *self.rules.write().unwrap_or_else(|e| e.into_inner()) = rules;
+        Ok(())
+    }
+
+    /// Denied domains are always rejected, an empty allowlist allows all other domains
+    pub(crate) fn is_allowed(&self, domain: &str) -> bool {
+        let domain = domain.to_ascii_lowercase();
+        let rules = self.rules.read().unwrap_or_else(|e| e.into_inner());
+        if rules.deny.iter().any(|pattern| pattern.matches(&domain)) {
+            return false;
+        }
+        rules.allow.is_empty() || rules.allow.iter().any(|pattern| pattern.matches(&domain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomainPattern, OriginFilter};
+    use crate::config::fpush_config::OriginDomainSettings;
+
+    fn settings(allow: &[&str], deny: &[&str]) -> OriginDomainSettings {
+        serde_json::from_value(serde_json::json!({ "allow": allow, "deny": deny })).unwrap()
+    }
+
+    #[test]
+    fn parse_patterns() {
+        assert_eq!(
+            DomainPattern::parse("Example.org").unwrap(),
+            DomainPattern::Exact("example.org".to_string())
+        );
+        assert_eq!(
+            DomainPattern::parse("*.example.org").unwrap(),
+            DomainPattern::Subdomains(".example.org".to_string())
+        );
+        assert!(DomainPattern::parse("*.").is_err());
+        assert!(DomainPattern::parse("push.*.org").is_err());
+        assert!(DomainPattern::parse("").is_err());
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let filter = OriginFilter::new(&settings(&[], &[])).unwrap();
+        assert!(filter.is_allowed("example.org"));
+
+        let filter = OriginFilter::new(&settings(
+            &["example.org", "*.example.com"],
+            &["spam.example.com"],
+        ))
+        .unwrap();
+        assert!(filter.is_allowed("example.org"));
+        assert!(filter.is_allowed("EXAMPLE.org"));
+        assert!(!filter.is_allowed("sub.example.org"));
+        assert!(filter.is_allowed("chat.example.com"));
+        assert!(!filter.is_allowed("example.com"));
+        assert!(!filter.is_allowed("spam.example.com"));
+        assert!(!filter.is_allowed("badexample.com"));
+    }
+
+    #[test]
+    fn reload_keeps_rules_on_error() {
+        let filter = OriginFilter::new(&settings(&[], &["example.org"])).unwrap();
+        assert!(filter.reload(&settings(&["*"], &[])).is_err());
+        assert!(!filter.is_allowed("example.org"));
+        filter.reload(&settings(&[], &[])).unwrap();
+        assert!(filter.is_allowed("example.org"));
+    }
+}
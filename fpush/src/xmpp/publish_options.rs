@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use crate::error::{PublishOptionsError, Result};
+
+use xmpp_parsers::data_forms::DataForm;
+
+const PUBLISH_OPTIONS_FORM_TYPE: &str = "http://jabber.org/protocol/pubsub#publish-options";
+/// upper bound to reject abusive forms, far above what XEP-0357 servers send
+const MAX_PUBLISH_OPTIONS: usize = 64;
+
+/// Publish-options of a push request.
+/// `pushModule` selects the push module, all other fields are collected into `options`.
+#[derive(Debug, Default)]
+pub(crate) struct PublishOptions {
+    pub(crate) push_module: Option<String>,
+    pub(crate) options: HashMap<String, String>,
+}
+
+impl PublishOptions {
+    pub(crate) fn parse(form: Option<DataForm>) -> Result<Self> {
+        let mut publish_options = Self::default();
+        let form = match form {
+            Some(form) => form,
+            None => return Ok(publish_options),
+        };
+        if let Some(form_type) = form.form_type {
+            if form_type != PUBLISH_OPTIONS_FORM_TYPE {
+                return Err(PublishOptionsError::WrongFormType(form_type).into());
+            }
+        }
+        if form.fields.len() > MAX_PUBLISH_OPTIONS {
+            return Err(PublishOptionsError::TooManyFields(MAX_PUBLISH_OPTIONS).into());
+        }
+        for field in form.fields {
+            let var = field.var.ok_or(PublishOptionsError::MissingVar)?;
+            // only the first value of multi value fields is used
+            let value = field.values.into_iter().next().unwrap_or_default();
+            match var.as_str() {
+                "FORM_TYPE" => {}
+                "pushModule" => {
+                    if publish_options.push_module.is_some() {
+                        return Err(PublishOptionsError::DuplicateField(var).into());
+                    }
+                    // an empty pushModule selects the default push module
+                    publish_options.push_module = Some(value).filter(|value| !value.is_empty());
+                }
+                _ => {
+                    if publish_options.options.contains_key(&var) {
+                        return Err(PublishOptionsError::DuplicateField(var).into());
+                    }
+                    publish_options.options.insert(var, value);
+                }
+            }
+        }
+        Ok(publish_options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublishOptions;
+    use crate::error::{Error, PublishOptionsError};
+
+    use xmpp::agent::Element;
+    use xmpp_parsers::data_forms::DataForm;
+
+    fn form(fields: &str) -> Option<DataForm> {
+        let xml = format!(
+            "<x xmlns='jabber:x:data' type='submit'>\
+             <field var='FORM_TYPE' type='hidden'><value>http://jabber.org/protocol/pubsub#publish-options</value></field>\
+             {}</x>",
+            fields
+        );
+        Some(DataForm::try_from(xml.parse::<Element>().unwrap()).unwrap())
+    }
+
+    #[test]
+    fn collects_unknown_fields() {
+        let publish_options = PublishOptions::parse(form(
+            "<field var='pushModule'><value>monalProdiOS</value></field>\
+             <field var='secret'><value>s3cr3t</value></field>\
+             <field var='vendor-option'><value>1</value></field>",
+        ))
+        .unwrap();
+        assert_eq!(publish_options.push_module.as_deref(), Some("monalProdiOS"));
+        assert_eq!(publish_options.options.len(), 2);
+        assert_eq!(publish_options.options["secret"], "s3cr3t");
+    }
+
+    #[test]
+    fn missing_push_module_selects_default() {
+        let publish_options =
+            PublishOptions::parse(form("<field var='secret'><value>s3cr3t</value></field>"))
+                .unwrap();
+        assert!(publish_options.push_module.is_none());
+        assert!(PublishOptions::parse(None).unwrap().push_module.is_none());
+    }
+
+    #[test]
+    fn rejects_duplicate_fields() {
+        let result = PublishOptions::parse(form(
+            "<field var='pushModule'><value>a</value></field>\
+             <field var='pushModule'><value>b</value></field>",
+        ));
+        assert!(matches!(
+            result,
+            Err(Error::PublishOptions(PublishOptionsError::DuplicateField(
+                _
+            )))
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use fpush_push::PushSummary;
+
+use log::debug;
+use xmpp_parsers::data_forms::DataForm;
+use xmpp_parsers::ns;
+use xmpp_parsers::pubsub::pubsub::Publish;
+
+const PUSH_NS: &str = "urn:xmpp:push:0";
+const PUSH_SUMMARY_FORM_TYPE: &str = "urn:xmpp:push:summary";
+
+/// Extract the XEP-0357 summary form from the `notification` element of the published item.
+/// Missing or malformed summaries result in an empty summary.
+pub(crate) fn parse_push_summary(publish: &Publish) -> PushSummary {
+    let mut summary = PushSummary::default();
+    let form = match publish
+        .items
+        .first()
+        .and_then(|item| item.payload.as_ref())
+        .filter(|payload| payload.is("notification", PUSH_NS))
+        .and_then(|notification| notification.get_child("x", ns::DATA_FORMS))
+        .map(|form| DataForm::try_from(form.clone()))
+    {
+        Some(Ok(form)) if form.form_type.as_deref() == Some(PUSH_SUMMARY_FORM_TYPE) => form,
+        Some(Err(e)) => {
+            debug!("Could not parse push summary: {}", e);
+            return summary;
+        }
+        _ => return summary,
+    };
+    for field in form.fields {
+        let value = match field.values.into_iter().next() {
+            Some(value) => value,
+            None => continue,
+        };
+        match field.var.as_deref() {
+            Some("message-count") => summary.message_count = value.parse().ok(),
+            Some("last-message-sender") => summary.last_message_sender = Some(value),
+            Some("last-message-body") => summary.last_message_body = Some(value),
+            Some("pending-subscription-count") => {
+                summary.pending_subscription_count = value.parse().ok()
+            }
+            _ => {}
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xmpp::agent::Element;
+
+    fn parse(notification: &str) -> PushSummary {
+        let publish: Element = format!(
+            "<publish xmlns='http://jabber.org/protocol/pubsub' node='token'><item>{}</item></publish>",
+            notification
+        )
+        .parse()
+        .unwrap();
+        parse_push_summary(&Publish::try_from(publish).unwrap())
+    }
+
+    fn summary_form(form_type: &str, fields: &str) -> String {
+        format!(
+            "<notification xmlns='urn:xmpp:push:0'><x xmlns='jabber:x:data' type='submit'>\
+            <field var='FORM_TYPE' type='hidden'><value>{}</value></field>{}</x></notification>",
+            form_type, fields
+        )
+    }
+
+    #[test]
+    fn missing_form() {
+        assert_eq!(
+            parse("<notification xmlns='urn:xmpp:push:0'/>"),
+            PushSummary::default()
+        );
+    }
+
+    #[test]
+    fn wrong_form_type() {
+        assert_eq!(
+            parse(&summary_form(
+                "urn:example:other",
+                "<field var='message-count'><value>3</value></field>"
+            )),
+            PushSummary::default()
+        );
+    }
+
+    #[test]
+    fn non_numeric_count() {
+        assert_eq!(
+            parse(&summary_form(
+                PUSH_SUMMARY_FORM_TYPE,
+                "<field var='message-count'><value>many</value></field>\
+                <field var='pending-subscription-count'><value>-1</value></field>"
+            )),
+            PushSummary::default()
+        );
+    }
+
+    #[test]
+    fn all_fields() {
+        assert_eq!(
+            parse(&summary_form(
+                PUSH_SUMMARY_FORM_TYPE,
+                "<field var='message-count'><value>3</value></field>\
+                <field var='last-message-sender'><value>juliet@example.org/balcony</value></field>\
+                <field var='last-message-body'><value>Wherefore art thou, Romeo?</value></field>\
+                <field var='pending-subscription-count'><value>1</value></field>"
+            )),
+            PushSummary {
+                message_count: Some(3),
+                last_message_sender: Some("juliet@example.org/balcony".to_string()),
+                last_message_body: Some("Wherefore art thou, Romeo?".to_string()),
+                pending_subscription_count: Some(1),
+            }
+        );
+    }
+}
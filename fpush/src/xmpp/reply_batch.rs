@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::config::fpush_config::ReplyBatchSettings;
+use crate::xmpp::connector::XmppConnection;
+use crate::xmpp::reply_queue::{QueuedReply, ReplyReceiver};
+use crate::xmpp::server_status::ServerStatus;
+
+use futures::SinkExt;
+use log::{error, warn};
+use tokio::time::Instant;
+
+/// Gathers queued replies so they can be written with a single flush
+pub(crate) struct ReplyBatcher {
+    max_size: usize,
+    window: Duration,
+    max_reply_age: Duration,
+}
+
+impl ReplyBatcher {
+    pub(crate) fn new(settings: &ReplyBatchSettings, max_reply_age: Duration) -> Self {
+        Self {
+            max_size: (*settings.max_size()).max(1),
+            window: *settings.window(),
+            max_reply_age,
+        }
+    }
+
+    /// Collect further replies after `first` until the batch is full or the window elapsed
+    pub(crate) async fn collect(
+        &self,
+        first: QueuedReply,
+        out_recv: &mut ReplyReceiver,
+    ) -> Vec<QueuedReply> {
+        let mut batch = Vec::with_capacity(self.max_size.min(64));
+        batch.push(first);
+        let deadline = Instant::now() + self.window;
+        while batch.len() < self.max_size {
+            match out_recv.try_recv() {
+                Ok(reply) => batch.push(reply),
+                Err(_) if self.window.is_zero() => break,
+                Err(_) => match tokio::time::timeout_at(deadline, out_recv.recv()).await {
+                    Ok(Some(reply)) => batch.push(reply),
+                    Ok(None) | Err(_) => break,
+                },
+            }
+        }
+        batch
+    }
+
+    /// Write all replies of a batch and flush the connection once.
+    /// Replies queued longer than the configured maximal age are dropped.
+    /// Returns the replies that could not be written, in their original order
+    pub(crate) async fn send_batch(
+        &self,
+        conn: &mut XmppConnection,
+        batch: Vec<QueuedReply>,
+        status: &ServerStatus,
+    ) -> Vec<QueuedReply> {
+        let mut fed = Vec::with_capacity(batch.len());
+        let mut batch = batch.into_iter();
+        while let Some(reply) = batch.next() {
+            if reply.age() > self.max_reply_age {
+                let reply = reply.into_iq();
+                warn!(
+                    "{}: Dropping reply {} to {}, it was queued longer than {}s",
+                    status.name(),
+                    reply.id,
+                    reply.to.map(|to| to.to_string()).unwrap_or_default(),
+                    self.max_reply_age.as_secs()
+                );
+                status.reply_expired();
+                continue;
+            }
+            if let Err(e) = conn.feed(reply.iq().clone().into()).await {
+                error!("{}: Could not reply iq: {}", status.name(), e);
+                fed.push(reply);
+                fed.extend(batch);
+                return fed;
+            }
+            fed.push(reply);
+        }
+        if fed.is_empty() {
+            return fed;
+        }
+        if let Err(e) = conn.flush().await {
+            error!(
+                "{}: Could not flush {} iq replies: {}",
+                status.name(),
+                fed.len(),
+                e
+            );
+            return fed;
+        }
+        status.reply_batch_sent(fed.len() as u64);
+        Vec::new()
+    }
+}
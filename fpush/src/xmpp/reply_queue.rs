@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::config::fpush_config::{FpushComponentSettings, TimeoutConfig};
+use crate::xmpp::connector::XmppConnection;
+use crate::xmpp::reply_batch::ReplyBatcher;
+use crate::xmpp::server_status::ServerStatus;
+
+use log::warn;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::time::Instant;
+use xmpp_parsers::iq::Iq;
+
+pub(crate) type ReplyReceiver = mpsc::Receiver<QueuedReply>;
+
+/// Reply waiting to be written to the XMPP server
+pub(crate) struct QueuedReply {
+    iq: Iq,
+    queued_at: Instant,
+}
+
+impl QueuedReply {
+    fn new(iq: Iq) -> Self {
+        Self {
+            iq,
+            queued_at: Instant::now(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn age(&self) -> Duration {
+        self.queued_at.elapsed()
+    }
+
+    #[inline(always)]
+    pub(crate) fn iq(&self) -> &Iq {
+        &self.iq
+    }
+
+    #[inline(always)]
+    pub(crate) fn into_iq(self) -> Iq {
+        self.iq
+    }
+}
+
+/// Queue of replies to one XMPP server. It outlives single connections,
+/// hence requests finishing during a reconnect are answered on the new connection
+#[derive(Clone)]
+pub(crate) struct ReplySender(mpsc::Sender<QueuedReply>);
+
+impl ReplySender {
+    pub(crate) async fn send(&self, iq: Iq) -> Result<(), SendError<QueuedReply>> {
+        self.0.send(QueuedReply::new(iq)).await
+    }
+
+    pub(crate) fn try_send(&self, iq: Iq) -> Result<(), TrySendError<QueuedReply>> {
+        self.0.try_send(QueuedReply::new(iq))
+    }
+}
+
+enum DrainEvent {
+    Reply(QueuedReply),
+    RequestsFinished,
+    DeadlineReached,
+}
+
+/// Reply path of one XMPP server, created once and reused by every connection
+pub(crate) struct ReplyQueue {
+    sender: ReplySender,
+    receiver: ReplyReceiver,
+    /// replies that could not be written, retried on the next connection
+    unsent: VecDeque<QueuedReply>,
+    batcher: ReplyBatcher,
+    shutdown_deadline: Duration,
+}
+
+impl ReplyQueue {
+    pub(crate) fn new(component_config: &FpushComponentSettings, timeout: &TimeoutConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(3000);
+        Self {
+            sender: ReplySender(sender),
+            receiver,
+            unsent: VecDeque::new(),
+            batcher: ReplyBatcher::new(component_config.reply_batch(), *timeout.max_reply_age()),
+            shutdown_deadline: *timeout.shutdown_deadline(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn sender(&self) -> &ReplySender {
+        &self.sender
+    }
+
+    /// Next reply to write, replies left unsent by a failed connection come first
+    #[inline(always)]
+    pub(crate) async fn recv(&mut self) -> Option<QueuedReply> {
+        match self.unsent.pop_front() {
+            Some(reply) => Some(reply),
+            None => self.receiver.recv().await,
+        }
+    }
+
+    /// Write `first` together with the replies queued after it.
+    /// Returns false if the connection failed, the unsent replies are then kept
+    /// for the next connection until they exceed the maximal reply age
+    pub(crate) async fn send(
+        &mut self,
+        first: QueuedReply,
+        conn: &mut XmppConnection,
+        status: &ServerStatus,
+    ) -> bool {
+        let batch = self.batcher.collect(first, &mut self.receiver).await;
+        let unsent = self.batcher.send_batch(conn, batch, status).await;
+        if unsent.is_empty() {
+            return true;
+        }
+        warn!(
+            "{}: Keeping {} unsent replies for the next connection",
+            status.name(),
+            unsent.len()
+        );
+        for reply in unsent.into_iter().rev() {
+            self.unsent.push_front(reply);
+        }
+        false
+    }
+
+    /// Reply to all requests still in flight until the shutdown deadline is reached
+    pub(crate) async fn drain(&mut self, conn: &mut XmppConnection, status: &ServerStatus) {
+        let deadline = Instant::now() + self.shutdown_deadline;
+        loop {
+            let event = tokio::select! {
+                Some(reply) = self.recv() => DrainEvent::Reply(reply),
+                _ = status.requests_finished() => DrainEvent::RequestsFinished,
+                _ = tokio::time::sleep_until(deadline) => DrainEvent::DeadlineReached,
+            };
+            match event {
+                DrainEvent::Reply(reply) => {
+                    if !self.send(reply, conn, status).await {
+                        return;
+                    }
+                }
+                DrainEvent::RequestsFinished => break,
+                DrainEvent::DeadlineReached => {
+                    warn!(
+                        "{}: Shutdown deadline reached with {} requests in flight",
+                        status.name(),
+                        status.in_flight_requests()
+                    );
+                    break;
+                }
+            }
+        }
+        // replies of requests finished since the last batch
+        while let Some(reply) = self
+            .unsent
+            .pop_front()
+            .or_else(|| self.receiver.try_recv().ok())
+        {
+            if !self.send(reply, conn, status).await {
+                return;
+            }
+        }
+    }
+}
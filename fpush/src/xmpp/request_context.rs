@@ -0,0 +1,34 @@
+use crate::xmpp::adhoc::AdminCommands;
+use crate::xmpp::origin_filter::OriginFilter;
+use crate::xmpp::server_status::ServerStatus;
+use fpush_push::FpushPushArc;
+
+use std::sync::Arc;
+
+use derive_getters::Getters;
+
+/// State shared by the request handlers of all XMPP connections
+#[derive(Getters)]
+pub(crate) struct RequestContext {
+    push_modules: FpushPushArc,
+    admin_commands: AdminCommands,
+    origin_filter: OriginFilter,
+    /// status of every configured XMPP server connection
+    connections: Vec<Arc<ServerStatus>>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        push_modules: FpushPushArc,
+        admin_commands: AdminCommands,
+        origin_filter: OriginFilter,
+        connections: Vec<Arc<ServerStatus>>,
+    ) -> Self {
+        Self {
+            push_modules,
+            admin_commands,
+            origin_filter,
+            connections,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::config::fpush_config::FpushComponentSettings;
+
+use log::{info, warn};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Connection state and counters of a single XMPP server connection
+pub(crate) struct ServerStatus {
+    name: String,
+    connected: AtomicBool,
+    connection_attempts: AtomicU64,
+    connection_failures: AtomicU64,
+    received_stanzas: AtomicU64,
+    sent_stanzas: AtomicU64,
+    reply_batches: AtomicU64,
+    largest_reply_batch: AtomicU64,
+    expired_replies: AtomicU64,
+    push_requests: AtomicU64,
+    push_errors: AtomicU64,
+    request_slots: Arc<Semaphore>,
+    max_in_flight_requests: usize,
+    peak_in_flight_requests: AtomicU64,
+    overloaded: AtomicBool,
+    overload_rejections: AtomicU64,
+}
+
+impl ServerStatus {
+    pub(crate) fn new(component: &FpushComponentSettings) -> Self {
+        Self {
+            name: format!(
+                "{}@{}:{}",
+                component.local_jid(),
+                component.server_hostname(),
+                component.server_port()
+            ),
+            connected: AtomicBool::new(false),
+            connection_attempts: AtomicU64::new(0),
+            connection_failures: AtomicU64::new(0),
+            received_stanzas: AtomicU64::new(0),
+            sent_stanzas: AtomicU64::new(0),
+            reply_batches: AtomicU64::new(0),
+            largest_reply_batch: AtomicU64::new(0),
+            expired_replies: AtomicU64::new(0),
+            push_requests: AtomicU64::new(0),
+            push_errors: AtomicU64::new(0),
+            request_slots: Arc::new(Semaphore::new(*component.max_in_flight_requests())),
+            max_in_flight_requests: *component.max_in_flight_requests(),
+            peak_in_flight_requests: AtomicU64::new(0),
+            overloaded: AtomicBool::new(false),
+            overload_rejections: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn connection_attempt(&self) {
+        self.connection_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_failed(&self) {
+        self.connected.store(false, Ordering::Relaxed);
+        self.connection_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn stanza_received(&self) {
+        self.received_stanzas.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reserve a slot for handling a stanza. Returns None if too many stanzas are in flight
+    pub(crate) fn try_acquire_request_slot(&self) -> Option<OwnedSemaphorePermit> {
+        match self.request_slots.clone().try_acquire_owned() {
+            Ok(permit) => {
+                self.peak_in_flight_requests
+                    .fetch_max(self.in_flight_requests() as u64, Ordering::Relaxed);
+                if self.overloaded.swap(false, Ordering::Relaxed) {
+                    info!(
+                        "{}: Recovered from overload, {} requests rejected in total",
+                        self.name,
+                        self.overload_rejections.load(Ordering::Relaxed)
+                    );
+                }
+                Some(permit)
+            }
+            Err(_) => {
+                self.overload_rejections.fetch_add(1, Ordering::Relaxed);
+                if !self.overloaded.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "{}: Overloaded with {} requests in flight, rejecting further requests",
+                        self.name, self.max_in_flight_requests
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn in_flight_requests(&self) -> usize {
+        self.max_in_flight_requests - self.request_slots.available_permits()
+    }
+
+    /// Wait until all request slots are released
+    pub(crate) async fn requests_finished(&self) {
+        let slots = u32::try_from(self.max_in_flight_requests).unwrap_or(u32::MAX);
+        // the slots are released right away, only the moment all of them were free matters
+        let _ = self.request_slots.acquire_many(slots).await;
+    }
+
+    /// Count a flushed batch of replies
+    #[inline(always)]
+    pub(crate) fn reply_batch_sent(&self, batch_size: u64) {
+        self.sent_stanzas.fetch_add(batch_size, Ordering::Relaxed);
+        self.reply_batches.fetch_add(1, Ordering::Relaxed);
+        self.largest_reply_batch
+            .fetch_max(batch_size, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn reply_expired(&self) {
+        self.expired_replies.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn push_handled(&self, success: bool) {
+        self.push_requests.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.push_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl std::fmt::Display for ServerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reply_batches = self.reply_batches.load(Ordering::Relaxed);
+        let avg_reply_batch = if reply_batches == 0 {
+            0.0
+        } else {
+            self.sent_stanzas.load(Ordering::Relaxed) as f64 / reply_batches as f64
+        };
+        write!(
+            f,
+            "{}: connected={} connection_attempts={} connection_failures={} received_stanzas={} sent_stanzas={} reply_batches={} avg_reply_batch={:.1} largest_reply_batch={} expired_replies={} push_requests={} push_errors={} in_flight_requests={}/{} peak_in_flight_requests={} overload_rejections={}",
+            self.name,
+            self.is_connected(),
+            self.connection_attempts.load(Ordering::Relaxed),
+            self.connection_failures.load(Ordering::Relaxed),
+            self.received_stanzas.load(Ordering::Relaxed),
+            self.sent_stanzas.load(Ordering::Relaxed),
+            reply_batches,
+            avg_reply_batch,
+            self.largest_reply_batch.load(Ordering::Relaxed),
+            self.expired_replies.load(Ordering::Relaxed),
+            self.push_requests.load(Ordering::Relaxed),
+            self.push_errors.load(Ordering::Relaxed),
+            self.in_flight_requests(),
+            self.max_in_flight_requests,
+            self.peak_in_flight_requests.load(Ordering::Relaxed),
+            self.overload_rejections.load(Ordering::Relaxed),
+        )
+    }
+}
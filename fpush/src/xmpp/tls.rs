@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use crate::config::fpush_config::{ComponentTlsMode, ComponentTlsSettings, FpushComponentSettings};
+use crate::error::{Error, Result, TlsHandshakeError};
+
+use log::debug;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::crypto::{
+    verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use tokio_rustls::TlsConnector;
+
+/// TLS settings of a single component connection, prepared once on startup
+pub(crate) struct ComponentTlsConnector {
+    mode: ComponentTlsMode,
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl ComponentTlsConnector {
+    pub(crate) fn new(
+        component: &FpushComponentSettings,
+        tls_settings: &ComponentTlsSettings,
+    ) -> Result<Self> {
+        let server_name = tls_settings
+            .server_name()
+            .clone()
+            .unwrap_or_else(|| component.server_hostname().to_string());
+        let server_name = ServerName::try_from(server_name)
+            .map_err(|e| Error::Config(format!("Invalid TLS server name: {}", e)))?;
+
+        let verifier = PinnedCertVerifier::new(tls_settings)?;
+        let config_builder = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+        let client_config = match (
+            tls_settings.client_cert_path(),
+            tls_settings.client_key_path(),
+        ) {
+            (Some(cert_path), Some(key_path)) => config_builder
+                .with_client_auth_cert(load_certs(cert_path)?, load_private_key(key_path)?)
+                .map_err(|e| Error::Config(format!("Invalid TLS client certificate: {}", e)))?,
+            (None, None) => config_builder.with_no_client_auth(),
+            _ => {
+                return Err(Error::Config(
+                    "clientCertPath and clientKeyPath must be set together".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            mode: *tls_settings.mode(),
+            connector: TlsConnector::from(Arc::new(client_config)),
+            server_name,
+        })
+    }
+
+    pub(crate) fn mode(&self) -> ComponentTlsMode {
+        self.mode
+    }
+
+    /// Run the TLS handshake on top of an already established tcp connection
+    pub(crate) async fn handshake(&self, stream: TcpStream) -> Result<TlsStream<TcpStream>> {
+        debug!("Starting TLS handshake with {:?}", self.server_name);
+        self.connector
+            .connect(self.server_name.clone(), stream)
+            .await
+            .map_err(|e| TlsHandshakeError::Handshake(e).into())
+    }
+}
+
+/// Verifies the server certificate against the configured CA bundle and/or
+/// the pinned certificate fingerprints
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    webpki: Option<Arc<WebPkiServerVerifier>>,
+    pins: Vec<Vec<u8>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertVerifier {
+    fn new(tls_settings: &ComponentTlsSettings) -> Result<Self> {
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+
+        let webpki = match tls_settings.ca_bundle_path() {
+            Some(ca_bundle_path) => {
+                let mut root_store = RootCertStore::empty();
+                for cert in load_certs(ca_bundle_path)? {
+                    root_store.add(cert).map_err(|e| {
+                        Error::Config(format!("Invalid CA in {}: {}", ca_bundle_path, e))
+                    })?;
+                }
+                let verifier = WebPkiServerVerifier::builder_with_provider(
+                    Arc::new(root_store),
+                    provider.clone(),
+                )
+                .build()
+                .map_err(|e| Error::Config(format!("Invalid CA bundle: {}", e)))?;
+                Some(verifier)
+            }
+            None => None,
+        };
+
+        let pins = tls_settings
+            .pinned_certificates()
+            .iter()
+            .map(|pin| {
+                hex::decode(pin.replace(':', ""))
+                    .ok()
+                    .filter(|digest| digest.len() == 32)
+                    .ok_or_else(|| {
+                        Error::Config(format!("Invalid SHA-256 certificate pin: {}", pin))
+                    })
+            })
+            .collect::<Result<Vec<Vec<u8>>>>()?;
+
+        if webpki.is_none() && pins.is_empty() {
+            return Err(Error::Config(
+                "TLS requires caBundlePath or pinnedCertificates".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            webpki,
+            pins,
+            provider,
+        })
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        if let Some(webpki) = &self.webpki {
+            webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )?;
+        }
+        if !self.pins.is_empty() {
+            let fingerprint = Sha256::digest(end_entity.as_ref());
+            if !self.pins.iter().any(|pin| pin[..] == fingerprint[..]) {
+                return Err(tokio_rustls::rustls::Error::InvalidCertificate(
+                    CertificateError::ApplicationVerificationFailure,
+                ));
+            }
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<std::io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(Error::Config(format!("No certificates found in {}", path)));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| Error::Config(format!("No private key found in {}", path)))
+}
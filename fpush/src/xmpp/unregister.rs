@@ -0,0 +1,118 @@
+use crate::error::{Error, Result};
+use crate::xmpp::publish_options::PublishOptions;
+use fpush_push::{PushRequest, PushSummary};
+
+use xmpp::agent::Element;
+use xmpp_parsers::{data_forms::DataForm, jid::Jid, ns};
+
+/// Push module and request of a pubsub owner `delete` or `purge`, or of a pubsub `retract`.
+/// XEP-0357 servers may send these when a user disables push.
+/// Like publish requests, they select the push module and carry its `secret` with
+/// publish-options, either as `<publish-options/>` child or as data form of the pubsub element.
+pub(crate) fn parse_unregister_request(
+    iq_payload: &Element,
+    from: &Jid,
+) -> Option<Result<(String, PushRequest)>> {
+    let pubsub_ns = if iq_payload.is("pubsub", ns::PUBSUB_OWNER) {
+        ns::PUBSUB_OWNER
+    } else if iq_payload.is("pubsub", ns::PUBSUB) {
+        ns::PUBSUB
+    } else {
+        return None;
+    };
+    let request = iq_payload.children().find(|child| {
+        if pubsub_ns == ns::PUBSUB_OWNER {
+            child.is("delete", ns::PUBSUB_OWNER) || child.is("purge", ns::PUBSUB_OWNER)
+        } else {
+            child.is("retract", ns::PUBSUB)
+        }
+    })?;
+    let token = request.attr("node").filter(|node| !node.is_empty())?;
+    Some(parse_options(iq_payload, pubsub_ns).map(|publish_options| {
+        (
+            publish_options
+                .push_module
+                .unwrap_or_else(|| "default".to_string()),
+            PushRequest::new(
+                token.to_string(),
+                from.domain().to_string(),
+                PushSummary::default(),
+                publish_options.options,
+            ),
+        )
+    }))
+}
+
+fn parse_options(iq_payload: &Element, pubsub_ns: &str) -> Result<PublishOptions> {
+    let parent = iq_payload
+        .get_child("publish-options", pubsub_ns)
+        .unwrap_or(iq_payload);
+    let form = match parent.get_child("x", ns::DATA_FORMS) {
+        Some(form) => {
+            Some(DataForm::try_from(form.clone()).map_err(|_| Error::PubSubInvalidFormat)?)
+        }
+        None => None,
+    };
+    PublishOptions::parse(form)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_unregister_request;
+
+    use xmpp::agent::Element;
+    use xmpp_parsers::jid::Jid;
+
+    fn parse(xml: &str) -> Option<(String, String, Option<String>)> {
+        let element = xml.parse::<Element>().unwrap();
+        let from = Jid::new("example.org").unwrap();
+        parse_unregister_request(&element, &from).map(|request| {
+            let (module_id, request) = request.unwrap();
+            (
+                module_id,
+                request.token().to_string(),
+                request.options().get("secret").cloned(),
+            )
+        })
+    }
+
+    #[test]
+    fn unregister_requests() {
+        assert_eq!(
+            parse("<pubsub xmlns='http://jabber.org/protocol/pubsub#owner'><delete node='token'/></pubsub>"),
+            Some(("default".to_string(), "token".to_string(), None))
+        );
+        assert_eq!(
+            parse("<pubsub xmlns='http://jabber.org/protocol/pubsub#owner'><purge node='token'/></pubsub>"),
+            Some(("default".to_string(), "token".to_string(), None))
+        );
+        assert_eq!(
+            parse("<pubsub xmlns='http://jabber.org/protocol/pubsub'><retract node='token'><item id='1'/></retract></pubsub>"),
+            Some(("default".to_string(), "token".to_string(), None))
+        );
+        assert_eq!(
+            parse("<pubsub xmlns='http://jabber.org/protocol/pubsub'><publish node='token'/></pubsub>"),
+            None
+        );
+        assert_eq!(
+            parse("<pubsub xmlns='http://jabber.org/protocol/pubsub#owner'><delete/></pubsub>"),
+            None
+        );
+    }
+
+    #[test]
+    fn unregister_request_options() {
+        let options = "<x xmlns='jabber:x:data' type='submit'>\
+            <field var='FORM_TYPE'><value>http://jabber.org/protocol/pubsub#publish-options</value></field>\
+            <field var='pushModule'><value>demo</value></field>\
+            <field var='secret'><value>abc</value></field></x>";
+        assert_eq!(
+            parse(&format!("<pubsub xmlns='http://jabber.org/protocol/pubsub'><retract node='token'/><publish-options>{}</publish-options></pubsub>", options)),
+            Some(("demo".to_string(), "token".to_string(), Some("abc".to_string())))
+        );
+        assert_eq!(
+            parse(&format!("<pubsub xmlns='http://jabber.org/protocol/pubsub#owner'><delete node='token'/>{}</pubsub>", options)),
+            Some(("demo".to_string(), "token".to_string(), Some("abc".to_string())))
+        );
+    }
+}